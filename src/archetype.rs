@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    behaviour::{BehaviourProfile, EnemyBehaviour},
+    boss::{Boss, BossDefinition},
+    collision::Faction,
+    damage::{Armor, Resistances},
+    enemy::{AttackPattern, ContactDamage, Enemy, EnemyAnimationStates, EnemyAttack, EnemyHealth, EnemySpriteAnimationStates},
+    game::{AnimationTimer, OnGameScreen},
+    player::{AnimationIndices, Facing, SpriteFacing},
+    spritesheet::{get_enemy_sprite_animation_states, get_sprite_atlas_layout, get_sprite_texture_handle, SpriteCollection, TextureAtlases},
+    status_effect::StatusEffects,
+    SCALE,
+};
+
+const ENEMY_ARCHETYPES_PATH: &str = "./assets/data/enemies.json";
+
+pub struct ArchetypePlugin;
+
+impl Plugin for ArchetypePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(EnemyArchetypes::default());
+        app.add_systems(Startup, load_enemy_archetypes);
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct ArchetypeSprites {
+    pub idle: String,
+    pub run: String,
+    /// Falls back to the idle animation, most enemy sheets have no hit frames.
+    #[serde(default)]
+    pub hit: Option<String>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ArchetypeCollider {
+    pub half_width: f32,
+    pub half_height: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ArchetypeContactDamage {
+    pub damage: f32,
+    pub knockback: f32,
+    pub radius: f32,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct ArchetypeAttack {
+    pub pattern: AttackPattern,
+    pub range: f32,
+    pub damage: f32,
+    pub knockback: f32,
+    pub windup: f32,
+    pub cooldown: f32,
+}
+
+/// One kind of enemy as described in `assets/data/enemies.json`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct EnemyArchetype {
+    pub id: String,
+    pub health: f32,
+    pub sprites: ArchetypeSprites,
+    #[serde(default = "default_sprite_scale")]
+    pub sprite_scale: f32,
+    pub collider: ArchetypeCollider,
+    #[serde(default)]
+    pub ai: BehaviourProfile,
+    #[serde(default)]
+    pub contact_damage: Option<ArchetypeContactDamage>,
+    #[serde(default)]
+    pub attack: Option<ArchetypeAttack>,
+    #[serde(default)]
+    pub resistances: Resistances,
+    #[serde(default)]
+    pub armor: f32,
+    /// Rolled when the enemy dies, see `assets/data/loot_tables.json`.
+    #[serde(default)]
+    pub loot_table: Option<String>,
+    /// Present on bosses, whose phases and scripted attacks replace the regular attack.
+    #[serde(default)]
+    pub boss: Option<BossDefinition>,
+}
+
+fn default_sprite_scale() -> f32 {
+    SCALE / 1.2
+}
+
+#[derive(Resource, Default)]
+pub struct EnemyArchetypes {
+    pub archetypes: HashMap<String, EnemyArchetype>,
+}
+
+impl EnemyArchetypes {
+    pub fn get(&self, id: &str) -> Option<&EnemyArchetype> {
+        self.archetypes.get(id)
+    }
+}
+
+/// Which archetype an enemy was spawned from.
+#[derive(Component, Clone, Debug)]
+pub struct ArchetypeId(pub String);
+
+fn load_enemy_archetypes(
+    mut enemy_archetypes: ResMut<EnemyArchetypes>,
+) {
+    let mut file = File::open(ENEMY_ARCHETYPES_PATH).expect("Failed to open enemy archetypes file");
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).expect("Failed to read enemy archetypes file");
+    let archetypes: Vec<EnemyArchetype> = serde_json::from_str(&contents).expect("Failed to parse enemy archetypes");
+
+    for archetype in archetypes {
+        println!("Loaded enemy archetype {}", archetype.id);
+        enemy_archetypes.archetypes.insert(archetype.id.clone(), archetype);
+    }
+}
+
+/// Spawns an enemy of the given archetype. Returns `None` if the archetype or its sprites do not exist.
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    enemy_archetypes: &EnemyArchetypes,
+    texture_atlas: &TextureAtlases,
+    sprite_collection: &SpriteCollection,
+    archetype_id: &str,
+    position: Vec2,
+) -> Option<Entity> {
+    let Some(archetype) = enemy_archetypes.get(archetype_id) else {
+        println!("Unknown enemy archetype {}", archetype_id);
+        return None;
+    };
+    let sprites = &archetype.sprites;
+
+    // All animations are indices into the atlas of the idle animation
+    let texture = get_sprite_texture_handle(sprites.idle.clone(), texture_atlas, sprite_collection)?;
+    let atlas_layout = get_sprite_atlas_layout(sprites.idle.clone(), texture_atlas, sprite_collection)?;
+    let hit_sprite = sprites.hit.clone().unwrap_or_else(|| sprites.idle.clone());
+    for sprite in [&sprites.run, &hit_sprite] {
+        if get_sprite_texture_handle(sprite.clone(), texture_atlas, sprite_collection).as_ref() != Some(&texture) {
+            println!("Sprite {} of archetype {} is not in the same atlas as {}", sprite, archetype_id, sprites.idle);
+        }
+    }
+
+    let available_states = vec![
+        get_enemy_sprite_animation_states(EnemyAnimationStates::IDLE, sprites.idle.clone(), sprite_collection),
+        get_enemy_sprite_animation_states(EnemyAnimationStates::RUNNING, sprites.run.clone(), sprite_collection),
+        get_enemy_sprite_animation_states(EnemyAnimationStates::HIT, hit_sprite, sprite_collection),
+    ];
+    let idle_indices = &available_states[0].1;
+    let animation_indices = AnimationIndices {
+        first: *idle_indices.first()?,
+        last: *idle_indices.last()?,
+    };
+
+    let mut enemy = commands.spawn((
+        SpriteSheetBundle {
+            texture,
+            atlas: TextureAtlas {
+                layout: atlas_layout,
+                index: animation_indices.first,
+            },
+            transform: Transform {
+                translation: position.extend(5.0),
+                rotation: Quat::default(),
+                scale: Vec3::new(archetype.sprite_scale, archetype.sprite_scale, 1.0),
+            },
+            ..Default::default()
+        },
+        SpriteFacing { facing: Facing::RIGHT },
+        EnemySpriteAnimationStates {
+            current_state: EnemyAnimationStates::IDLE,
+            available_states,
+            changed: false,
+        },
+        animation_indices,
+        AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
+        Enemy,
+        ArchetypeId(archetype.id.clone()),
+        EnemyHealth::new(archetype.health),
+        StatusEffects::default(),
+        (archetype.resistances, Armor(archetype.armor)),
+        EnemyBehaviour::new(archetype.ai, position),
+        (
+            RigidBody::Dynamic,
+            LockedAxes::ROTATION_LOCKED,
+            Faction::Enemy,
+            Faction::Enemy.collision_groups(),
+            ColliderMassProperties::Density(2.0),
+            Damping {
+                linear_damping: 2.0,
+                ..Default::default()
+            },
+            Collider::cuboid(archetype.collider.half_width, archetype.collider.half_height),
+            ActiveEvents::COLLISION_EVENTS,
+        ),
+        Name::new(format!("Enemy ({})", archetype.id)),
+        OnGameScreen,
+    ));
+
+    if let Some(contact_damage) = archetype.contact_damage {
+        enemy.insert(ContactDamage {
+            damage: contact_damage.damage,
+            knockback: contact_damage.knockback,
+            radius: contact_damage.radius,
+        });
+    }
+    if let Some(attack) = archetype.attack {
+        enemy.insert(EnemyAttack::new(attack.pattern, attack.range, attack.damage, attack.knockback, attack.windup, attack.cooldown));
+    }
+    if let Some(boss) = &archetype.boss {
+        enemy.insert(Boss::new(boss.clone(), archetype.ai.speed));
+    }
+
+    Some(enemy.id())
+}
@@ -0,0 +1,298 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    boss::Boss,
+    collision::has_line_of_sight,
+    combat::HitStun,
+    enemy::{Enemy, EnemyAnimationStates, EnemyAttack, EnemyHealth, EnemySpriteAnimationStates},
+    navigation::NavigationPath,
+    player::{ControllablePlayer, PlayerDead},
+    rng::{GameRng, RngStream},
+    status_effect::StatusEffects,
+    steering::{arrival, avoid_obstacles, separation, SpatialHash},
+    AppState,
+};
+
+/// Distance at which an enemy counts as having reached a point it is walking to.
+const ARRIVAL_DISTANCE: f32 = 10.0;
+/// Enemies keep chasing until the player is this much further away than their perception radius.
+const LOSE_INTEREST_FACTOR: f32 = 1.5;
+const PATROL_SPEED_FACTOR: f32 = 0.5;
+const FLEE_SPEED_FACTOR: f32 = 1.3;
+/// How long an enemy looks around the last seen position before heading home.
+const SEARCH_SECONDS: f32 = 2.0;
+/// Enemies start slowing down this far from where they want to stop.
+const ARRIVAL_SLOWING_RADIUS: f32 = 40.0;
+/// Enemies closer together than this push each other apart.
+const SEPARATION_RADIUS: f32 = 70.0;
+const SEPARATION_WEIGHT: f32 = 1.2;
+const OBSTACLE_LOOK_AHEAD: f32 = 60.0;
+const AVOIDANCE_WEIGHT: f32 = 0.8;
+/// Below this speed an enemy is drawn standing still, so the separation jitter does not flicker the animation.
+const MIN_MOVING_SPEED: f32 = 5.0;
+
+pub struct BehaviourPlugin;
+
+impl Plugin for BehaviourPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (update_behaviour_state, move_enemies).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BehaviourState {
+    Idle,
+    Patrol { target: Vec2 },
+    Chase,
+    Attack,
+    /// Walking to where the player was last seen after losing sight of them.
+    Investigate,
+    Flee,
+    ReturnToSpawn,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct BehaviourProfile {
+    pub perception_radius: f32,
+    /// How far from its spawn point an enemy will follow the player before giving up.
+    pub leash_distance: f32,
+    pub attack_range: f32,
+    /// Ranged enemies back away while the player is closer than this, 0.0 to stand their ground.
+    pub preferred_distance: f32,
+    pub speed: f32,
+    pub patrol_radius: f32,
+    /// Fraction of max health below which the enemy runs away, 0.0 to never flee.
+    pub flee_below_health: f32,
+}
+
+impl Default for BehaviourProfile {
+    fn default() -> Self {
+        BehaviourProfile {
+            perception_radius: 300.0,
+            leash_distance: 600.0,
+            attack_range: 150.0,
+            preferred_distance: 0.0,
+            speed: 80.0,
+            patrol_radius: 100.0,
+            flee_below_health: 0.0,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+pub struct EnemyBehaviour {
+    pub state: BehaviourState,
+    pub profile: BehaviourProfile,
+    pub spawn_point: Vec2,
+    pub last_seen_position: Option<Vec2>,
+    idle_timer: Timer,
+    search_timer: Timer,
+}
+
+impl EnemyBehaviour {
+    pub fn new(profile: BehaviourProfile, spawn_point: Vec2) -> Self {
+        EnemyBehaviour {
+            state: BehaviourState::Idle,
+            profile,
+            spawn_point,
+            last_seen_position: None,
+            idle_timer: Timer::from_seconds(2.0, TimerMode::Once),
+            search_timer: Timer::from_seconds(SEARCH_SECONDS, TimerMode::Once),
+        }
+    }
+
+    /// Where the current state wants to walk to. Fleeing moves directly away from the player instead.
+    pub fn destination(&self, player_position: Option<Vec2>) -> Option<Vec2> {
+        match self.state {
+            BehaviourState::Patrol { target } => Some(target),
+            BehaviourState::Chase => player_position,
+            BehaviourState::Investigate => self.last_seen_position,
+            BehaviourState::ReturnToSpawn => Some(self.spawn_point),
+            _ => None,
+        }
+    }
+
+    fn set_state(&mut self, state: BehaviourState) {
+        if self.state == state {
+            return;
+        }
+        debug!("Enemy behaviour {:?} -> {:?}", self.state, state);
+        match state {
+            BehaviourState::Idle => self.idle_timer.reset(),
+            BehaviourState::Investigate => self.search_timer.reset(),
+            _ => {},
+        }
+        self.state = state;
+    }
+}
+
+fn update_behaviour_state(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    mut enemy_query: Query<(&Transform, &mut EnemyBehaviour, Option<&mut EnemyHealth>, Option<&EnemyAttack>), With<Enemy>>,
+    player_query: Query<&Transform, (With<ControllablePlayer>, Without<PlayerDead>, Without<Enemy>)>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let player_position = player_query.get_single().ok().map(|transform| transform.translation.truncate());
+    let rng = game_rng.stream(RngStream::Ai);
+
+    for (transform, mut behaviour, mut health, attack) in &mut enemy_query {
+        // Never change state in the middle of a telegraphed attack
+        if attack.is_some_and(|attack| attack.winding_up) {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let profile = behaviour.profile;
+        let distance_from_spawn = position.distance(behaviour.spawn_point);
+        let distance_to_player = player_position.map(|player| position.distance(player));
+        let health_fraction = health.as_ref().map_or(1.0, |health| health.current / health.max);
+
+        // Enemies already fighting keep track of the player a little further than they first notice them
+        let engaged = matches!(behaviour.state, BehaviourState::Chase | BehaviourState::Attack);
+        let sight_radius = if engaged { profile.perception_radius * LOSE_INTEREST_FACTOR } else { profile.perception_radius };
+        let sees_player = match (player_position, distance_to_player) {
+            (Some(player), Some(distance)) => distance <= sight_radius && has_line_of_sight(&rapier_context, position, player),
+            _ => false,
+        };
+        if sees_player {
+            behaviour.last_seen_position = player_position;
+        }
+
+        match behaviour.state {
+            BehaviourState::Idle | BehaviourState::Patrol { .. } | BehaviourState::Investigate if sees_player => {
+                behaviour.set_state(BehaviourState::Chase);
+            },
+            BehaviourState::Idle => {
+                behaviour.idle_timer.tick(time.delta());
+                if behaviour.idle_timer.finished() && profile.patrol_radius > 0.0 {
+                    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+                    let distance = rng.gen_range(0.0..profile.patrol_radius);
+                    let target = behaviour.spawn_point + Vec2::new(angle.cos(), angle.sin()) * distance;
+                    behaviour.set_state(BehaviourState::Patrol { target });
+                }
+            },
+            BehaviourState::Patrol { target } => {
+                if position.distance(target) <= ARRIVAL_DISTANCE {
+                    behaviour.set_state(BehaviourState::Idle);
+                }
+            },
+            BehaviourState::Chase | BehaviourState::Attack => {
+                if health_fraction <= profile.flee_below_health && sees_player {
+                    behaviour.set_state(BehaviourState::Flee);
+                } else if distance_from_spawn > profile.leash_distance {
+                    behaviour.set_state(BehaviourState::ReturnToSpawn);
+                } else if !sees_player {
+                    // Lost sight of the player, go and look where they were last seen
+                    let state = if behaviour.last_seen_position.is_some() { BehaviourState::Investigate } else { BehaviourState::ReturnToSpawn };
+                    behaviour.set_state(state);
+                } else if distance_to_player.is_some_and(|distance| distance <= profile.attack_range) {
+                    behaviour.set_state(BehaviourState::Attack);
+                } else {
+                    behaviour.set_state(BehaviourState::Chase);
+                }
+            },
+            BehaviourState::Investigate => {
+                let Some(last_seen_position) = behaviour.last_seen_position else {
+                    behaviour.set_state(BehaviourState::ReturnToSpawn);
+                    continue;
+                };
+                if distance_from_spawn > profile.leash_distance {
+                    behaviour.set_state(BehaviourState::ReturnToSpawn);
+                } else if position.distance(last_seen_position) <= ARRIVAL_DISTANCE * 4.0 {
+                    // Look around for a moment before giving up
+                    behaviour.search_timer.tick(time.delta());
+                    if behaviour.search_timer.finished() {
+                        behaviour.set_state(BehaviourState::ReturnToSpawn);
+                    }
+                }
+            },
+            BehaviourState::Flee => {
+                let escaped = !sees_player && distance_to_player.map_or(true, |distance| distance > profile.perception_radius);
+                if escaped || distance_from_spawn > profile.leash_distance {
+                    behaviour.set_state(BehaviourState::ReturnToSpawn);
+                }
+            },
+            BehaviourState::ReturnToSpawn => {
+                if distance_from_spawn <= ARRIVAL_DISTANCE {
+                    // Leashed enemies reset so they cannot be worn down by kiting them back and forth
+                    if let Some(health) = health.as_mut() {
+                        health.current = health.max;
+                    }
+                    behaviour.last_seen_position = None;
+                    behaviour.set_state(BehaviourState::Idle);
+                }
+            },
+        }
+    }
+}
+
+fn move_enemies(
+    time: Res<Time>,
+    rapier_context: Res<RapierContext>,
+    spatial_hash: Res<SpatialHash>,
+    mut enemy_query: Query<(Entity, &mut Transform, &EnemyBehaviour, &mut EnemySpriteAnimationStates, &mut Sprite, Option<&StatusEffects>, Option<&EnemyAttack>, Option<&NavigationPath>, Option<&Boss>), (With<Enemy>, Without<HitStun>)>,
+    player_query: Query<&Transform, (With<ControllablePlayer>, Without<PlayerDead>, Without<Enemy>)>,
+) {
+    let player_position = player_query.get_single().ok().map(|transform| transform.translation.truncate());
+
+    for (entity, mut transform, behaviour, mut animation, mut sprite, status_effects, attack, path, boss) in &mut enemy_query {
+        if attack.is_some_and(|attack| attack.winding_up) || boss.is_some_and(Boss::is_telegraphing) {
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let max_speed = behaviour.profile.speed * status_effects.map_or(1.0, |effects| effects.movement_multiplier());
+        let destination = behaviour.destination(player_position);
+        // Follow the navigation path around walls, falling back to a straight line when there is none
+        let waypoint = destination
+            .map(|destination| path.and_then(NavigationPath::next_waypoint).unwrap_or(destination));
+        // The goal is where the enemy wants to come to a stop, if anywhere
+        let (direction, speed, goal) = match (behaviour.state, player_position, waypoint) {
+            (BehaviourState::Patrol { .. }, _, Some(waypoint)) => (waypoint - position, max_speed * PATROL_SPEED_FACTOR, destination),
+            (BehaviourState::Chase | BehaviourState::Investigate, _, Some(waypoint)) => (waypoint - position, max_speed, destination),
+            // Casters keep their distance so the player has to close in on them
+            (BehaviourState::Attack, Some(player), _) if position.distance(player) < behaviour.profile.preferred_distance => (position - player, max_speed, None),
+            // Enemies without a ranged or telegraphed attack keep pressing in to land contact damage
+            (BehaviourState::Attack, Some(player), _) if attack.is_none() => (player - position, max_speed, Some(player)),
+            (BehaviourState::Flee, Some(player), _) => (position - player, max_speed * FLEE_SPEED_FACTOR, None),
+            (BehaviourState::ReturnToSpawn, _, Some(waypoint)) => (waypoint - position, max_speed, destination),
+            _ => (Vec2::ZERO, 0.0, None),
+        };
+
+        // Blend the desired movement with crowd separation and wall avoidance so groups spread
+        // around the player instead of stacking on the same spot
+        let direction = direction.normalize_or_zero();
+        let arrival_factor = goal.map_or(1.0, |goal| arrival(position.distance(goal), ARRIVAL_SLOWING_RADIUS));
+        let mut velocity = direction * speed * arrival_factor;
+        velocity += separation(&spatial_hash, entity, position, SEPARATION_RADIUS) * max_speed * SEPARATION_WEIGHT;
+        if direction != Vec2::ZERO {
+            velocity += avoid_obstacles(&rapier_context, position, direction, OBSTACLE_LOOK_AHEAD) * max_speed * AVOIDANCE_WEIGHT;
+        }
+        let velocity = velocity.clamp_length_max(speed.max(max_speed));
+
+        // Face the player while attacking, otherwise face the direction of travel
+        let facing = match (behaviour.state, player_position) {
+            (BehaviourState::Attack, Some(player)) => player - position,
+            _ => velocity,
+        };
+        if facing.x != 0.0 {
+            sprite.flip_x = facing.x < 0.0;
+        }
+
+        let moving = velocity.length() > MIN_MOVING_SPEED;
+        let animation_state = if moving { EnemyAnimationStates::RUNNING } else { EnemyAnimationStates::IDLE };
+        if animation.current_state != animation_state {
+            animation.current_state = animation_state;
+            animation.changed = true;
+        }
+
+        if moving {
+            transform.translation += (velocity * time.delta_seconds()).extend(0.0);
+        }
+    }
+}
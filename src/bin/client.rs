@@ -1,20 +1,38 @@
 use bevy::input::common_conditions::input_toggle_active;
 use bevy::prelude::*;
 use bevy_ecs_ldtk::{LdtkPlugin, LevelSelection};
+use bevy_game_client::archetype::ArchetypePlugin;
+use bevy_game_client::behaviour::BehaviourPlugin;
+use bevy_game_client::boss::BossPlugin;
+use bevy_game_client::collision::CollisionPlugin;
+use bevy_game_client::combat::CombatPlugin;
 use bevy_game_client::cursor::CursorPlugin;
+use bevy_game_client::dungeon::DungeonPlugin;
 use bevy_game_client::enemy::EnemyPlugin;
 use bevy_game_client::game::GamePlugin;
+use bevy_game_client::gameover::GameOverPlugin;
 use bevy_game_client::healthbar::HealthBarPlugin;
+use bevy_game_client::hotbar::HotbarPlugin;
 use bevy_game_client::input::InputPlugin;
+use bevy_game_client::interaction::InteractionPlugin;
 use bevy_game_client::inventory::InventoryPlugin;
 use bevy_game_client::level::LevelPlugin;
+use bevy_game_client::loot::LootPlugin;
 use bevy_game_client::magic::MagicPlugin;
 use bevy_game_client::mainmenu::menu::MenuPlugin;
 use bevy_game_client::melee::MeleePlugin;
+use bevy_game_client::navigation::NavigationPlugin;
 use bevy_game_client::player::PlayerPlugin;
+use bevy_game_client::rng::RngPlugin;
 use bevy_game_client::chest::ChestPlugin;
+use bevy_game_client::door::DoorPlugin;
+use bevy_game_client::spawner::SpawnerPlugin;
 use bevy_game_client::splashscreen::splash::SplashPlugin;
+use bevy_game_client::status_effect::StatusEffectPlugin;
 use bevy_game_client::spritesheet::SpriteSheetPlugin;
+use bevy_game_client::steering::SteeringPlugin;
+use bevy_game_client::transition::LevelTransitionPlugin;
+use bevy_game_client::traps::TrapPlugin;
 use bevy_game_client::AppState;
 use bevy_game_client::debug::DebugPlugin;
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
@@ -29,8 +47,16 @@ fn main() {
 
     app.add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
         .add_plugins(DebugPlugin)
+        .add_plugins(RngPlugin)
         .add_plugins(EnemyPlugin)
+        .add_plugins(ArchetypePlugin)
+        .add_plugins(BehaviourPlugin)
+        .add_plugins(NavigationPlugin)
+        .add_plugins(BossPlugin)
+        .add_plugins(SpawnerPlugin)
+        .add_plugins(SteeringPlugin)
         .add_plugins(MagicPlugin)
+        .add_plugins(StatusEffectPlugin)
         .add_plugins(MenuPlugin)
         .add_plugins(SplashPlugin)
         .add_plugins(CursorPlugin)
@@ -39,15 +65,25 @@ fn main() {
         .add_plugins(GamePlugin)
         .add_plugins(PlayerPlugin)
         .add_plugins(HealthBarPlugin)
+        .add_plugins(HotbarPlugin)
         .add_plugins(InventoryPlugin)
         .add_plugins(RapierDebugRenderPlugin::default())
         
         // .add_plugins(NetworkPlugin)
         .add_plugins(LevelPlugin)
-        // .add_plugins(MeleePlugin)
+        .add_plugins(LevelTransitionPlugin)
+        .add_plugins(DungeonPlugin)
+        .add_plugins(TrapPlugin)
+        .add_plugins(MeleePlugin)
         .add_plugins(SpriteSheetPlugin)
+        .add_plugins(InteractionPlugin)
         .add_plugins(ChestPlugin)
+        .add_plugins(DoorPlugin)
+        .add_plugins(LootPlugin)
         .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
+        .add_plugins(CollisionPlugin)
+        .add_plugins(CombatPlugin)
+        .add_plugins(GameOverPlugin)
         .init_state::<AppState>();
 
         let mut rapier_config = RapierConfiguration::new(100.0);
@@ -1,8 +1,9 @@
 use std::{collections::HashMap, net::UdpSocket, time::SystemTime};
 
 use bevy::prelude::*;
-use bevy_game_client::{connection_config, ClientChannel, NetworkedEntities, Player, PlayerInput, PlayerPosition, ServerChannel, ServerMessages, Velocity, PROTOCOL_ID};
+use bevy_game_client::{connection_config, rng::{GameRng, RngPlugin, RngStream}, ClientChannel, NetworkedEntities, Player, PlayerInput, PlayerPosition, ServerChannel, ServerMessages, Velocity, PROTOCOL_ID};
 use bevy_renet::{renet::{transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig}, ClientId, RenetServer, ServerEvent}, transport::NetcodeServerPlugin, RenetServerPlugin};
+use rand::Rng;
 
 const PLAYER_SPEED: f32 = 500.0;
 
@@ -36,7 +37,8 @@ fn main() {
     let mut app = App::new();
 
     app.add_plugins(DefaultPlugins.set(ImagePlugin::default_nearest()))
-        .add_plugins(RenetServerPlugin);
+        .add_plugins(RenetServerPlugin)
+        .add_plugins(RngPlugin);
 
     app.insert_resource(ServerLobby::default());
     app.insert_resource(BotId(0));
@@ -47,7 +49,8 @@ fn main() {
     // app.add_systems(Startup, setup);
     // app.add_systems(Update, animate_sprite);
 
-    app.add_systems(Update, (server_update_system, server_network_sync, move_players_system, spawn_bot, apply_position_system));
+    // Both spawn players from RngStream::Spawning, so they draw in a fixed order
+    app.add_systems(Update, (server_update_system, server_network_sync, move_players_system, spawn_bot.after(server_update_system), apply_position_system));
 
     app.add_systems(FixedUpdate, apply_velocity_system);
 
@@ -82,7 +85,8 @@ fn server_update_system(
     mut lobby: ResMut<ServerLobby>,
     mut server: ResMut<RenetServer>,
     players: Query<(Entity, &Player, &Transform)>,
-    player_sprite: ResMut<PlayerSpriteAtlas>
+    player_sprite: ResMut<PlayerSpriteAtlas>,
+    mut game_rng: ResMut<GameRng>,
 ) {
     let animation_indices = AnimationIndices {first: 0, last: 3};
 
@@ -101,7 +105,8 @@ fn server_update_system(
                     server.send_message(*client_id, ServerChannel::ServerMessages, message);
                 }
 
-                let transform = Transform::from_xyz((fastrand::f32() - 0.5) * 40.0, (fastrand::f32() - 0.5) * 40.0, 0.0); 
+                let rng = game_rng.stream(RngStream::Spawning);
+                let transform = Transform::from_xyz((rng.gen::<f32>() - 0.5) * 40.0, (rng.gen::<f32>() - 0.5) * 40.0, 0.0);
                 let player_entity = commands.spawn(SpriteSheetBundle {
                     texture: player_sprite.image.clone(),
                     atlas: TextureAtlas {
@@ -192,13 +197,15 @@ fn spawn_bot(
     mut lobby: ResMut<ServerLobby>,
     mut server: ResMut<RenetServer>,
     mut bot_id: ResMut<BotId>,
+    mut game_rng: ResMut<GameRng>,
     mut commands: Commands,
 ) {
     if keyboard_input.just_pressed(KeyCode::Space) {
         let client_id = ClientId::from_raw(bot_id.0);
         bot_id.0 += 1;
         // Spawn new player
-        let transform = Transform::from_xyz((fastrand::f32() - 0.5) * 40., 0.51, (fastrand::f32() - 0.5) * 40.);
+        let rng = game_rng.stream(RngStream::Spawning);
+        let transform = Transform::from_xyz((rng.gen::<f32>() - 0.5) * 40., 0.51, (rng.gen::<f32>() - 0.5) * 40.);
         let player_entity = commands
             .spawn(Player { id: client_id })
             .id();
@@ -0,0 +1,456 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    archetype::{spawn_enemy, EnemyArchetypes},
+    behaviour::{BehaviourState, EnemyBehaviour},
+    collision::Faction,
+    combat::HitStun,
+    damage::Damage,
+    enemy::{Enemy, EnemyHealth},
+    game::OnGameScreen,
+    magic::{spawn_enemy_projectile, FireBallSpriteAtlas, IceSpikeSpriteAtlas, Spells},
+    navigation::NavigationObstacle,
+    player::{ControllablePlayer, PlayerDead},
+    spritesheet::{SpriteCollection, TextureAtlases},
+    AppState, FONT_PATH, SCALE,
+};
+
+const ARENA_DOOR_SPRITE_PATH: &str = "./textures/rpg/doors_leaf_closed.png";
+/// How far from the boss ring projectiles appear.
+const RING_SPAWN_OFFSET: f32 = 60.0;
+const BOSS_BAR_WIDTH: f32 = 400.0;
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(BossEncounter::default());
+        app.register_ldtk_entity::<ArenaDoorBundle>("ArenaDoor");
+        app.add_systems(OnEnter(AppState::InGame), setup_boss_health_bar);
+        app.add_systems(OnExit(AppState::InGame), reset_boss_encounter);
+        app.add_systems(Update, (
+            setup_arena_doors,
+            update_boss_encounter,
+            update_boss_phase,
+            boss_attack,
+            update_arena_doors,
+            update_boss_health_bar,
+        ).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum BossAttack {
+    /// Fire `count` projectiles evenly spaced around the boss.
+    ProjectileRing { spell: Spells, count: u32, speed: f32, damage: f32 },
+    /// Dash at the player, relying on contact damage to land the hit.
+    Charge { speed: f32 },
+    /// Call in minions until `count` of them are alive.
+    Summon { archetype: String, count: u32, radius: f32 },
+}
+
+impl BossAttack {
+    /// Colour the boss is tinted towards while telegraphing the attack.
+    fn telegraph_color(&self) -> Color {
+        match self {
+            BossAttack::ProjectileRing { spell, .. } => spell.damage_type().color(),
+            BossAttack::Charge { .. } => Color::rgb(1.0, 0.4, 0.4),
+            BossAttack::Summon { .. } => Color::rgb(0.7, 0.4, 1.0),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct BossPhase {
+    /// Fraction of max health at or below which this phase starts.
+    pub below_health: f32,
+    #[serde(default = "default_speed_multiplier")]
+    pub speed_multiplier: f32,
+    /// Seconds between the end of one attack and the telegraph of the next.
+    pub attack_interval: f32,
+    pub telegraph: f32,
+    /// Performed in order, looping.
+    pub attacks: Vec<BossAttack>,
+}
+
+fn default_speed_multiplier() -> f32 {
+    1.0
+}
+
+/// The `boss` section of an enemy archetype. Phases are listed from full health down.
+#[derive(Deserialize, Clone, Debug)]
+pub struct BossDefinition {
+    pub name: String,
+    pub phases: Vec<BossPhase>,
+}
+
+#[derive(Component)]
+pub struct Boss {
+    pub definition: BossDefinition,
+    pub phase: usize,
+    base_speed: f32,
+    attack_timer: Timer,
+    next_attack: usize,
+    /// Projectile rings fired this phase, used to alternate their rotation.
+    rings_fired: usize,
+    telegraph: Option<(BossAttack, Timer)>,
+}
+
+impl Boss {
+    pub fn new(definition: BossDefinition, base_speed: f32) -> Self {
+        let attack_interval = definition.phases.first().map_or(2.0, |phase| phase.attack_interval);
+        Boss {
+            definition,
+            phase: 0,
+            base_speed,
+            attack_timer: Timer::from_seconds(attack_interval, TimerMode::Once),
+            next_attack: 0,
+            rings_fired: 0,
+            telegraph: None,
+        }
+    }
+
+    pub fn current_phase(&self) -> Option<&BossPhase> {
+        self.definition.phases.get(self.phase)
+    }
+
+    /// Bosses stand still while telegraphing so the player can read the attack.
+    pub fn is_telegraphing(&self) -> bool {
+        self.telegraph.is_some()
+    }
+
+    fn enter_phase(&mut self, phase: usize) {
+        self.phase = phase;
+        self.next_attack = 0;
+        self.rings_fired = 0;
+        self.telegraph = None;
+        if let Some(phase) = self.current_phase() {
+            self.attack_timer = Timer::from_seconds(phase.attack_interval, TimerMode::Once);
+        }
+    }
+}
+
+/// Summoned by a boss, despawned along with it.
+#[derive(Component)]
+pub struct BossMinion {
+    pub boss: Entity,
+}
+
+/// The boss currently fighting the player, if any. Arena doors stay closed while it is set.
+#[derive(Resource, Default)]
+pub struct BossEncounter {
+    pub boss: Option<Entity>,
+}
+
+/// Doorway that closes while a boss fight is in progress, placed as "ArenaDoor" in the LDtk level.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct ArenaDoor;
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct ArenaDoorBundle {
+    arena_door: ArenaDoor,
+}
+
+#[derive(Component)]
+struct BossHealthBar;
+
+#[derive(Component)]
+struct BossHealthBarFill;
+
+#[derive(Component)]
+struct BossNameText;
+
+fn setup_arena_doors(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    door_query: Query<Entity, Added<ArenaDoor>>,
+) {
+    for entity in &door_query {
+        // Sized in level pixels, the level transform scales it up like the walls
+        commands.entity(entity).insert((
+            Sprite {
+                custom_size: Some(Vec2::new(16.0, 16.0)),
+                ..Default::default()
+            },
+            asset_server.load::<Image>(ARENA_DOOR_SPRITE_PATH),
+            Visibility::Hidden,
+            RigidBody::Fixed,
+            Collider::cuboid(8.0, 8.0),
+            Faction::Wall,
+            Faction::Wall.collision_groups(),
+            NavigationObstacle::new(Vec2::new(16.0, 16.0) * SCALE),
+            ColliderDisabled,
+        ));
+    }
+}
+
+fn setup_boss_health_bar(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                top: Val::Px(10.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            visibility: Visibility::Hidden,
+            ..Default::default()
+        },
+        BossHealthBar,
+        OnGameScreen,
+    ))
+    .with_children(|parent| {
+        parent.spawn((
+            TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load(FONT_PATH),
+                    font_size: 20.0,
+                    ..Default::default()
+                },
+            ),
+            BossNameText,
+        ));
+        parent.spawn(NodeBundle {
+            style: Style {
+                width: Val::Px(BOSS_BAR_WIDTH),
+                height: Val::Px(14.0),
+                margin: UiRect::top(Val::Px(4.0)),
+                ..Default::default()
+            },
+            background_color: Color::rgb(0.15, 0.05, 0.05).into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        ..Default::default()
+                    },
+                    background_color: Color::rgb(0.8, 0.1, 0.1).into(),
+                    ..Default::default()
+                },
+                BossHealthBarFill,
+            ));
+        });
+    });
+}
+
+fn reset_boss_encounter(
+    mut boss_encounter: ResMut<BossEncounter>,
+) {
+    boss_encounter.boss = None;
+}
+
+/// Starts the fight once a boss notices the player and ends it when the boss dies or gives up the chase.
+fn update_boss_encounter(
+    mut commands: Commands,
+    mut boss_encounter: ResMut<BossEncounter>,
+    mut boss_query: Query<(Entity, &EnemyBehaviour, &mut Boss, &mut Sprite)>,
+    minion_query: Query<(Entity, &BossMinion)>,
+) {
+    let Some(boss_entity) = boss_encounter.boss else {
+        for (entity, behaviour, boss, _) in &boss_query {
+            if matches!(behaviour.state, BehaviourState::Chase | BehaviourState::Attack) {
+                println!("Boss fight started: {}", boss.definition.name);
+                boss_encounter.boss = Some(entity);
+                break;
+            }
+        }
+        return;
+    };
+
+    let defeated = match boss_query.get_mut(boss_entity) {
+        Ok((_, behaviour, mut boss, mut sprite)) => {
+            if behaviour.state != BehaviourState::ReturnToSpawn {
+                return;
+            }
+            // The player escaped or died, the boss heals on the way home so the fight starts over
+            println!("Boss fight reset: {}", boss.definition.name);
+            boss.enter_phase(0);
+            sprite.color = Color::WHITE;
+            false
+        },
+        Err(_) => true,
+    };
+    if defeated {
+        println!("Boss defeated");
+    }
+
+    for (entity, minion) in &minion_query {
+        if minion.boss == boss_entity {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    boss_encounter.boss = None;
+}
+
+fn update_boss_phase(
+    mut boss_query: Query<(&mut Boss, &EnemyHealth, &mut EnemyBehaviour)>,
+) {
+    for (mut boss, health, mut behaviour) in &mut boss_query {
+        let health_fraction = health.current / health.max;
+        let phase = boss.definition.phases.iter()
+            .rposition(|phase| health_fraction <= phase.below_health)
+            .unwrap_or(0);
+        // Phases only advance, healing back up does not undo them until the fight resets
+        if phase > boss.phase {
+            println!("{} enters phase {}", boss.definition.name, phase + 1);
+            boss.enter_phase(phase);
+        }
+        if let Some(phase) = boss.current_phase() {
+            behaviour.profile.speed = boss.base_speed * phase.speed_multiplier;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn boss_attack(
+    mut commands: Commands,
+    time: Res<Time>,
+    boss_encounter: Res<BossEncounter>,
+    enemy_archetypes: Res<EnemyArchetypes>,
+    texture_atlas: Res<TextureAtlases>,
+    sprite_collection: Res<SpriteCollection>,
+    fireball_sprite: Res<FireBallSpriteAtlas>,
+    icespike_sprite: Res<IceSpikeSpriteAtlas>,
+    mut boss_query: Query<(Entity, &Transform, &mut Boss, &mut Sprite), (With<Enemy>, Without<HitStun>)>,
+    minion_query: Query<&BossMinion>,
+    player_query: Query<&Transform, (With<ControllablePlayer>, Without<PlayerDead>, Without<Enemy>)>,
+) {
+    let Some(boss_entity) = boss_encounter.boss else {
+        return;
+    };
+    let Ok((entity, transform, mut boss, mut sprite)) = boss_query.get_mut(boss_entity) else {
+        return;
+    };
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let position = transform.translation.truncate();
+
+    let Some((attack, mut telegraph)) = boss.telegraph.take() else {
+        boss.attack_timer.tick(time.delta());
+        if !boss.attack_timer.finished() {
+            return;
+        }
+        let Some(phase) = boss.current_phase() else {
+            return;
+        };
+        if phase.attacks.is_empty() {
+            return;
+        }
+        let attack = phase.attacks[boss.next_attack % phase.attacks.len()].clone();
+        let telegraph = Timer::from_seconds(phase.telegraph, TimerMode::Once);
+        boss.next_attack += 1;
+        boss.telegraph = Some((attack, telegraph));
+        return;
+    };
+
+    telegraph.tick(time.delta());
+    let tint = attack.telegraph_color().rgba_to_vec4().lerp(Vec4::ONE, telegraph.fraction_remaining());
+    sprite.color = Color::rgba(tint.x, tint.y, tint.z, 1.0);
+    if !telegraph.finished() {
+        boss.telegraph = Some((attack, telegraph));
+        return;
+    }
+
+    sprite.color = Color::WHITE;
+    boss.attack_timer.reset();
+
+    match attack {
+        BossAttack::ProjectileRing { spell, count, speed, damage } => {
+            // Alternate rings are rotated by half a step so standing in a gap only works once
+            let step = TAU / count.max(1) as f32;
+            let rotation = if boss.rings_fired % 2 == 1 { step / 2.0 } else { 0.0 };
+            boss.rings_fired += 1;
+            for index in 0..count {
+                let angle = rotation + step * index as f32;
+                let direction = Vec2::new(angle.cos(), angle.sin());
+                spawn_enemy_projectile(
+                    &mut commands,
+                    &fireball_sprite,
+                    &icespike_sprite,
+                    spell,
+                    position + direction * RING_SPAWN_OFFSET,
+                    direction,
+                    speed,
+                    Damage::new(damage, spell.damage_type()),
+                    150.0,
+                );
+            }
+        },
+        BossAttack::Charge { speed } => {
+            let direction = (player_transform.translation.truncate() - position).normalize_or_zero();
+            commands.entity(entity).insert(Velocity::linear(direction * speed));
+        },
+        BossAttack::Summon { archetype, count, radius } => {
+            let alive = minion_query.iter().filter(|minion| minion.boss == entity).count() as u32;
+            let to_spawn = count.saturating_sub(alive);
+            for index in 0..to_spawn {
+                let angle = TAU * index as f32 / to_spawn as f32;
+                let spawn_position = position + Vec2::new(angle.cos(), angle.sin()) * radius;
+                if let Some(minion) = spawn_enemy(&mut commands, &enemy_archetypes, &texture_atlas, &sprite_collection, &archetype, spawn_position) {
+                    commands.entity(minion).insert(BossMinion { boss: entity });
+                }
+            }
+        },
+    }
+}
+
+fn update_arena_doors(
+    mut commands: Commands,
+    boss_encounter: Res<BossEncounter>,
+    mut door_query: Query<(Entity, &mut Visibility), With<ArenaDoor>>,
+    new_door_query: Query<(), Added<ArenaDoor>>,
+) {
+    if !boss_encounter.is_changed() && new_door_query.is_empty() {
+        return;
+    }
+    let locked = boss_encounter.boss.is_some();
+    for (entity, mut visibility) in &mut door_query {
+        if locked {
+            *visibility = Visibility::Visible;
+            commands.entity(entity).remove::<ColliderDisabled>();
+        } else {
+            *visibility = Visibility::Hidden;
+            commands.entity(entity).insert(ColliderDisabled);
+        }
+    }
+}
+
+fn update_boss_health_bar(
+    boss_encounter: Res<BossEncounter>,
+    boss_query: Query<(&Boss, &EnemyHealth)>,
+    mut bar_query: Query<&mut Visibility, With<BossHealthBar>>,
+    mut fill_query: Query<&mut Style, With<BossHealthBarFill>>,
+    mut text_query: Query<&mut Text, With<BossNameText>>,
+) {
+    let boss = boss_encounter.boss.and_then(|entity| boss_query.get(entity).ok());
+
+    for mut visibility in &mut bar_query {
+        *visibility = if boss.is_some() { Visibility::Visible } else { Visibility::Hidden };
+    }
+    let Some((boss, health)) = boss else {
+        return;
+    };
+
+    for mut style in &mut fill_query {
+        style.width = Val::Percent((health.current / health.max).clamp(0.0, 1.0) * 100.0);
+    }
+    for mut text in &mut text_query {
+        text.sections[0].value = boss.definition.name.clone();
+    }
+}
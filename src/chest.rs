@@ -1,87 +1,69 @@
+use std::collections::HashSet;
+
 use bevy::prelude::*;
 
-use crate::{player::ControllablePlayer, AppState, SCALE};
+use crate::{
+    game::OnGameScreen,
+    interaction::{InteractAction, InteractEvent, Interactable, Interactor},
+    inventory::Inventory,
+    loot::{spawn_loot, LootTables},
+    rng::{GameRng, RngStream},
+    AppState, SCALE,
+};
 
 pub struct ChestPlugin;
 
-const KEYBOARD_SPRITES: &str = "./sprites/keyboard/Keyboard.png";
 const CHEST_SPRITES: &str = "./sprites/inventory/chest/chests.png";
 
 impl Plugin for ChestPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(AppState::LoadingScreen), load_sprite_atlases);
-        app.add_systems(OnEnter(AppState::InGame), setup);
-        app.add_systems(Update, (interaction_system).run_if(in_state(AppState::InGame)));
-        app.insert_resource(KeyboardSpriteAtlas::default());
+        app.add_systems(Update, (open_chests, update_chest_sprites).chain().run_if(in_state(AppState::InGame)));
         app.insert_resource(ChestSpriteAtlas::default());
-        app.insert_resource(SpawnedEntity::default());
-        app.add_event::<ChestInteractionEvent>();
     }
 }
 
 #[derive(Resource, Default, Clone)]
-struct KeyboardSpriteAtlas {
-    layout: Handle<TextureAtlasLayout>,
-    image: Handle<Image>,
-}
-
-#[derive(Resource, Default, Clone)]
-struct ChestSpriteAtlas {
+pub struct ChestSpriteAtlas {
     layout: Handle<TextureAtlasLayout>,
     image: Handle<Image>,
 }
 
 #[derive(Component, Default)]
-struct Chest {
+pub struct Chest {
     state: ChestState,
+    pub loot_table: String,
+    /// Id of the key item needed to open it, used up when the chest opens.
+    pub key: Option<String>,
 }
 
 #[derive(Default, PartialEq)]
 enum ChestState {
     #[default]
     CLOSED,
+    /// Closed and the nearest thing a player can interact with.
     CLOSED_INSPECTED,
     OPENED,
-    OPENED_INSPECTED,
 }
 
-#[derive(Component, Clone)]
-struct SpriteIndex(usize);
-
-#[derive(Event)]
-struct ChestInteractionEvent {
-    pub triggered: bool,
-}
-
-impl ChestInteractionEvent {
-    fn new(triggered: bool) -> Self {
-        return ChestInteractionEvent {
-            triggered,
-        };
+impl ChestState {
+    fn sprite_index(&self) -> usize {
+        match self {
+            ChestState::CLOSED => 0,
+            ChestState::CLOSED_INSPECTED => 4,
+            ChestState::OPENED => 3,
+        }
     }
 }
 
-#[derive(Resource, Default)]
-struct SpawnedEntity {
-    entity: Option<Entity>,
-}
+#[derive(Component, Clone)]
+struct SpriteIndex(usize);
 
 fn load_sprite_atlases(
-    mut keyboard_sprite_atlas: ResMut<KeyboardSpriteAtlas>,
     mut chest_sprite_atlas: ResMut<ChestSpriteAtlas>,
     asset_server: Res<AssetServer>,
     mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
 ) {
-    //Load Keyboard sprites
-    {
-        let image_handle: Handle<Image> = asset_server.load(KEYBOARD_SPRITES);
-        let texture_atlas = TextureAtlasLayout::from_grid(Vec2::new(16.0, 16.0), 8, 14, None, None);
-        let texture_atlas_handle = texture_atlases.add(texture_atlas);
-
-        keyboard_sprite_atlas.image = image_handle;
-        keyboard_sprite_atlas.layout = texture_atlas_handle;
-    }
-
     //Load Chest Sprite
     {
         let image_handle: Handle<Image> = asset_server.load(CHEST_SPRITES);
@@ -93,14 +75,16 @@ fn load_sprite_atlases(
     }
 }
 
-fn setup(
-    mut commands: Commands,
-    chest_sprite_atlas: Res<ChestSpriteAtlas>,
-) {
-
-    let mut sprite_index = SpriteIndex(0);
-
-    let chest_entity = commands.spawn((
+pub fn spawn_chest(
+    commands: &mut Commands,
+    chest_sprite_atlas: &ChestSpriteAtlas,
+    position: Vec2,
+    loot_table: String,
+    key: Option<String>,
+) -> Entity {
+    let sprite_index = SpriteIndex(0);
+
+    commands.spawn((
         SpriteSheetBundle {
             sprite: Sprite {
                 flip_x: false,
@@ -112,97 +96,65 @@ fn setup(
                 index: sprite_index.0,
             },
             transform: Transform {
-                translation: Vec3 { x: 1400.0, y: 1400.0, z: 5.0 },
+                translation: position.extend(5.0),
                 rotation: Quat::default(),
                 scale: Vec3 { x: SCALE/1.2, y: SCALE/1.2, z: 1.0 },
             },
             ..Default::default()
         },
-        Chest::default(),
+        Interactable::new(InteractAction::OpenChest, if key.is_some() { "Unlock" } else { "Open" }),
+        Chest { loot_table, key, ..Default::default() },
         sprite_index.clone(),
-    )).id();
+        OnGameScreen,
+    )).id()
 }
 
-fn interaction_system(
+fn open_chests(
     mut commands: Commands,
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    player_query: Query<&Transform, With<ControllablePlayer>>,
-    mut chest_query: Query<(&Transform, &mut SpriteIndex, &mut TextureAtlas, &mut Chest), With<Chest>>,
-    mut interaction_event: EventWriter<ChestInteractionEvent>,
-    keyboard_sprites: Res<KeyboardSpriteAtlas>,
-    mut spawned_entity: ResMut<SpawnedEntity>,
+    asset_server: Res<AssetServer>,
+    loot_tables: Res<LootTables>,
+    mut game_rng: ResMut<GameRng>,
+    mut inventory: ResMut<Inventory>,
+    mut interact_events: EventReader<InteractEvent>,
+    mut chest_query: Query<(&Transform, &mut Chest, &mut Interactable)>,
 ) {
-    for player_transform in &player_query {
-        for (chest_transform, mut index, mut sprite, mut chest) in &mut chest_query {
-            let distance = player_transform.translation.distance(chest_transform.translation);
-            if distance < 100.0 {
-                if chest.state == ChestState::CLOSED {
-                    println!("Setting state to closed inspected");
-                    chest.state = ChestState::CLOSED_INSPECTED;
-                    index.0 = 4;
-                    sprite.index = index.0;
-                    spawn_interaction_key(&mut commands, &keyboard_sprites, chest_transform, &mut spawned_entity);
-                }
-                if chest.state == ChestState::OPENED {
-                    chest.state = ChestState::OPENED_INSPECTED;
-                }
-                if keyboard_input.just_pressed(KeyCode::KeyE) {
-                    interaction_event.send(ChestInteractionEvent::new(true));
-                    println!("Sent a chest interaction event");
-                }
-            } 
-            else {
-                if chest.state == ChestState::CLOSED_INSPECTED {
-                    println!("Setting state to closed");
-                    chest.state = ChestState::CLOSED;
-                    index.0 = 0;
-                    sprite.index = index.0;
-                    despawn_interaction_key(&mut commands, &mut spawned_entity);
-                }
-            } 
+    for event in interact_events.read() {
+        let Ok((transform, mut chest, mut interactable)) = chest_query.get_mut(event.target) else {
+            continue;
+        };
+        if chest.state == ChestState::OPENED {
+            continue;
         }
+        if !inventory.use_key(chest.key.as_deref()) {
+            println!("Chest is locked, it needs {:?}", chest.key);
+            continue;
+        }
+
+        println!("Opening chest with loot table {}", chest.loot_table);
+        chest.state = ChestState::OPENED;
+        interactable.enabled = false;
+        spawn_loot(&mut commands, &asset_server, &loot_tables, &chest.loot_table, transform.translation.truncate(), game_rng.stream(RngStream::Chests));
     }
 }
 
-fn spawn_interaction_key(
-    commands: &mut Commands,
-    keyboard_sprites: &Res<KeyboardSpriteAtlas>,
-    transform: &Transform,
-    spawned_entity: &mut ResMut<SpawnedEntity>,
+/// Highlights the chests players are about to open and shows opened chests open.
+fn update_chest_sprites(
+    interactor_query: Query<&Interactor>,
+    mut chest_query: Query<(Entity, &mut SpriteIndex, &mut TextureAtlas, &mut Chest)>,
 ) {
-    let position = transform.translation + Vec3::new(40.0, 40.0, 0.0);
+    let targets: HashSet<Entity> = interactor_query.iter().filter_map(|interactor| interactor.target).collect();
 
-    let key_entity = commands.spawn((
-        SpriteSheetBundle {
-            sprite: Sprite {
-                flip_x: false,
-                ..Default::default()
-            },
-            texture: keyboard_sprites.image.clone(),
-            atlas: TextureAtlas {
-                layout: keyboard_sprites.layout.clone(),
-                index: 20,
-            },
-            transform: Transform {
-                translation: position,
-                rotation: Quat::default(),
-                scale: Vec3 { x: SCALE/2.0, y: SCALE/2.0, z: 1.0 },
-            },
-            ..Default::default()
-        },
-    )).id();
-
-    spawned_entity.entity = Some(key_entity);
-    println!("Spawned key entity");
-}
-
-fn despawn_interaction_key(
-    commands: &mut Commands,
-    spawned_entity: &mut ResMut<SpawnedEntity>,
-) {
-    if let Some(entity) = spawned_entity.entity {
-        commands.entity(entity).despawn();
-        spawned_entity.entity = None;
-        println!("Despawned key entity");
+    for (entity, mut index, mut sprite, mut chest) in &mut chest_query {
+        let state = match chest.state {
+            ChestState::OPENED => ChestState::OPENED,
+            _ if targets.contains(&entity) => ChestState::CLOSED_INSPECTED,
+            _ => ChestState::CLOSED,
+        };
+        // Compare against the sprite, `open_chests` sets the state of a chest it opens without touching it
+        if index.0 != state.sprite_index() {
+            index.0 = state.sprite_index();
+            sprite.index = index.0;
+            chest.state = state;
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,165 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+pub struct CollisionPlugin;
+
+pub const PLAYER_GROUP: Group = Group::GROUP_1;
+pub const ENEMY_GROUP: Group = Group::GROUP_2;
+pub const PROJECTILE_GROUP: Group = Group::GROUP_3;
+pub const WALL_GROUP: Group = Group::GROUP_4;
+pub const PICKUP_GROUP: Group = Group::GROUP_5;
+pub const ENEMY_PROJECTILE_GROUP: Group = Group::GROUP_6;
+pub const TRAP_PROJECTILE_GROUP: Group = Group::GROUP_7;
+pub const PLAYER_MELEE_GROUP: Group = Group::GROUP_8;
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FactionCollisionEvent>();
+        app.add_systems(Update, dispatch_collision_events);
+    }
+}
+
+/// Identifies which side of a collision an entity belongs to.
+///
+/// The component lives on the entity that owns the gameplay state (the player, an enemy, a spell),
+/// which may be the parent of the entity holding the actual `Collider`.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Faction {
+    Player,
+    Enemy,
+    Projectile,
+    /// Projectiles fired by enemies, they hit the player instead of enemies.
+    EnemyProjectile,
+    /// Fired by traps, they hit players and enemies alike.
+    TrapProjectile,
+    /// The player's sword, a sensor that only overlaps enemies.
+    PlayerMelee,
+    Wall,
+    Pickup,
+}
+
+impl Faction {
+    pub fn membership(&self) -> Group {
+        match self {
+            Faction::Player => PLAYER_GROUP,
+            Faction::Enemy => ENEMY_GROUP,
+            Faction::Projectile => PROJECTILE_GROUP,
+            Faction::EnemyProjectile => ENEMY_PROJECTILE_GROUP,
+            Faction::TrapProjectile => TRAP_PROJECTILE_GROUP,
+            Faction::PlayerMelee => PLAYER_MELEE_GROUP,
+            Faction::Wall => WALL_GROUP,
+            Faction::Pickup => PICKUP_GROUP,
+        }
+    }
+
+    pub fn filter(&self) -> Group {
+        match self {
+            Faction::Player => ENEMY_GROUP | WALL_GROUP | PICKUP_GROUP | ENEMY_PROJECTILE_GROUP | TRAP_PROJECTILE_GROUP,
+            Faction::Enemy => PLAYER_GROUP | ENEMY_GROUP | PROJECTILE_GROUP | WALL_GROUP | TRAP_PROJECTILE_GROUP | PLAYER_MELEE_GROUP,
+            Faction::Projectile => ENEMY_GROUP | WALL_GROUP,
+            Faction::EnemyProjectile => PLAYER_GROUP | WALL_GROUP,
+            Faction::TrapProjectile => PLAYER_GROUP | ENEMY_GROUP | WALL_GROUP,
+            Faction::PlayerMelee => ENEMY_GROUP,
+            Faction::Wall => Group::ALL,
+            Faction::Pickup => PLAYER_GROUP,
+        }
+    }
+
+    pub fn collision_groups(&self) -> CollisionGroups {
+        CollisionGroups::new(self.membership(), self.filter())
+    }
+
+    /// Groups whose contacts generate forces. Projectiles and pickups still report collisions
+    /// with everything in their filter but only physically push against walls.
+    pub fn solver_groups(&self) -> SolverGroups {
+        match self {
+            Faction::Projectile | Faction::EnemyProjectile | Faction::TrapProjectile => SolverGroups::new(self.membership(), WALL_GROUP),
+            Faction::Pickup => SolverGroups::new(self.membership(), Group::NONE),
+            Faction::Player => SolverGroups::new(self.membership(), ENEMY_GROUP | WALL_GROUP),
+            _ => SolverGroups::new(self.membership(), self.filter()),
+        }
+    }
+
+    /// Groups a character controller should treat as obstacles, the ones it would be pushed by.
+    pub fn movement_groups(&self) -> CollisionGroups {
+        CollisionGroups::new(self.membership(), self.solver_groups().filters)
+    }
+}
+
+/// A rapier collision translated to the entities and factions involved.
+///
+/// One event is sent for each ordering of the pair, so subscribers only have to check
+/// `entity`/`faction` for their own side.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct FactionCollisionEvent {
+    pub entity: Entity,
+    pub faction: Faction,
+    pub other: Entity,
+    pub other_faction: Faction,
+    pub started: bool,
+}
+
+impl FactionCollisionEvent {
+    pub fn is(&self, faction: Faction, other_faction: Faction) -> bool {
+        self.faction == faction && self.other_faction == other_faction
+    }
+}
+
+/// True when no wall collider blocks the straight line between two points.
+pub fn has_line_of_sight(rapier_context: &RapierContext, from: Vec2, to: Vec2) -> bool {
+    let offset = to - from;
+    let distance = offset.length();
+    if distance <= f32::EPSILON {
+        return true;
+    }
+    let filter = QueryFilter::new().groups(CollisionGroups::new(Group::ALL, WALL_GROUP));
+    rapier_context.cast_ray(from, offset / distance, distance, true, filter).is_none()
+}
+
+fn resolve_faction(
+    collider_entity: Entity,
+    faction_query: &Query<&Faction>,
+    parent_query: &Query<&Parent>,
+) -> Option<(Entity, Faction)> {
+    if let Ok(faction) = faction_query.get(collider_entity) {
+        return Some((collider_entity, *faction));
+    }
+    let parent = parent_query.get(collider_entity).ok()?.get();
+    faction_query.get(parent).ok().map(|faction| (parent, *faction))
+}
+
+fn dispatch_collision_events(
+    mut collision_events: EventReader<CollisionEvent>,
+    faction_query: Query<&Faction>,
+    parent_query: Query<&Parent>,
+    mut faction_collision_events: EventWriter<FactionCollisionEvent>,
+) {
+    for event in collision_events.read() {
+        let (collider_1, collider_2, started) = match event {
+            CollisionEvent::Started(entity_1, entity_2, _) => (*entity_1, *entity_2, true),
+            CollisionEvent::Stopped(entity_1, entity_2, _) => (*entity_1, *entity_2, false),
+        };
+
+        let (Some((entity_1, faction_1)), Some((entity_2, faction_2))) = (
+            resolve_faction(collider_1, &faction_query, &parent_query),
+            resolve_faction(collider_2, &faction_query, &parent_query),
+        ) else {
+            continue;
+        };
+
+        faction_collision_events.send(FactionCollisionEvent {
+            entity: entity_1,
+            faction: faction_1,
+            other: entity_2,
+            other_faction: faction_2,
+            started,
+        });
+        faction_collision_events.send(FactionCollisionEvent {
+            entity: entity_2,
+            faction: faction_2,
+            other: entity_1,
+            other_faction: faction_1,
+            started,
+        });
+    }
+}
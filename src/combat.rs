@@ -0,0 +1,200 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{damage::{calculate_damage, Armor, Damage, Resistances}, enemy::{spawn_damage_number, EnemyAnimationStates, EnemyHealth, EnemySpriteAnimationStates}, healthbar::PlayerHealth, player::{ControllablePlayer, PlayerAnimationStates, PlayerDead, PlayerDeathEvent, PlayerSpriteAnimationStates}, rng::{GameRng, RngStream}, AppState};
+
+pub struct CombatPlugin;
+
+const PLAYER_INVULNERABILITY_SECONDS: f32 = 1.0;
+const INVULNERABILITY_FLASH_SECONDS: f32 = 0.08;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<HitEvent>();
+        app.add_systems(Update, (apply_hit_events, tick_hit_stun, tick_knockback, tick_invulnerability).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+/// Sent by anything that hurts an entity. `apply_hit_events` resolves damage, knockback,
+/// hit-stun and invulnerability in one place.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct HitEvent {
+    pub target: Entity,
+    pub source_position: Vec3,
+    pub damage: Damage,
+    /// Speed in pixels per second the target is pushed away from `source_position`.
+    pub knockback: f32,
+    /// Seconds the target loses control and plays its HIT animation.
+    pub stun: f32,
+}
+
+#[derive(Component)]
+pub struct HitStun {
+    pub timer: Timer,
+}
+
+/// Knockback for kinematic bodies, which rapier does not push around. Dynamic bodies get their velocity set instead.
+#[derive(Component)]
+pub struct Knockback {
+    pub velocity: Vec2,
+    pub timer: Timer,
+}
+
+#[derive(Component)]
+pub struct Invulnerable {
+    pub timer: Timer,
+    flash_timer: Timer,
+}
+
+impl Invulnerable {
+    pub fn new(seconds: f32) -> Self {
+        Invulnerable {
+            timer: Timer::from_seconds(seconds, TimerMode::Once),
+            flash_timer: Timer::from_seconds(INVULNERABILITY_FLASH_SECONDS, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Takes `amount` off the player's health and starts the death sequence when it runs out.
+pub fn damage_player(
+    commands: &mut Commands,
+    player_health: &mut PlayerHealth,
+    death_events: &mut EventWriter<PlayerDeathEvent>,
+    player: Entity,
+    amount: f32,
+) {
+    // Already dying, e.g. a hit and a poison tick landing in the same frame
+    if player_health.0 == 0 {
+        return;
+    }
+    // Player health is whole half-hearts, so any damage costs at least one
+    let lost_health = (amount.round() as u32).max(1);
+    player_health.0 = player_health.0.saturating_sub(lost_health);
+    if player_health.0 == 0 {
+        println!("Player died");
+        commands.entity(player).insert(PlayerDead);
+        death_events.send(PlayerDeathEvent { player });
+    }
+}
+
+fn apply_hit_events(
+    mut commands: Commands,
+    mut hit_events: EventReader<HitEvent>,
+    asset_server: Res<AssetServer>,
+    mut player_health: ResMut<PlayerHealth>,
+    mut death_events: EventWriter<PlayerDeathEvent>,
+    mut game_rng: ResMut<GameRng>,
+    mut target_query: Query<(
+        &Transform,
+        Option<&mut EnemyHealth>,
+        Option<&mut EnemySpriteAnimationStates>,
+        Option<&mut PlayerSpriteAnimationStates>,
+        Option<&RigidBody>,
+        Option<&Resistances>,
+        Option<&Armor>,
+        Has<ControllablePlayer>,
+        Has<Invulnerable>,
+        Has<PlayerDead>,
+    )>,
+) {
+    let rng = game_rng.stream(RngStream::Combat);
+    for event in hit_events.read() {
+        let Ok((transform, enemy_health, enemy_animation, player_animation, rigid_body, resistances, armor, is_player, is_invulnerable, is_dead)) = target_query.get_mut(event.target) else {
+            continue;
+        };
+        if is_invulnerable || is_dead {
+            continue;
+        }
+
+        let result = calculate_damage(&event.damage, resistances, armor, rng);
+        if let Some(mut health) = enemy_health {
+            health.current -= result.amount;
+        }
+        if is_player && result.amount > 0.0 {
+            damage_player(&mut commands, &mut player_health, &mut death_events, event.target, result.amount);
+        }
+        if result.amount > 0.0 {
+            spawn_damage_number(&mut commands, &asset_server, &result, transform.translation.truncate().extend(7.0));
+        }
+
+        let direction = (transform.translation.truncate() - event.source_position.truncate()).normalize_or_zero();
+        if event.knockback > 0.0 {
+            if matches!(rigid_body, Some(RigidBody::Dynamic)) {
+                commands.entity(event.target).insert(Velocity::linear(direction * event.knockback));
+            } else {
+                commands.entity(event.target).insert(Knockback {
+                    velocity: direction * event.knockback,
+                    timer: Timer::from_seconds(event.stun.max(0.1), TimerMode::Once),
+                });
+            }
+        }
+
+        if event.stun > 0.0 {
+            commands.entity(event.target).insert(HitStun {
+                timer: Timer::from_seconds(event.stun, TimerMode::Once),
+            });
+            if let Some(mut animation) = enemy_animation {
+                animation.current_state = EnemyAnimationStates::HIT;
+                animation.changed = true;
+            }
+            if let Some(mut animation) = player_animation {
+                animation.current_state = PlayerAnimationStates::HIT;
+            }
+        }
+
+        if is_player {
+            commands.entity(event.target).insert(Invulnerable::new(PLAYER_INVULNERABILITY_SECONDS));
+        }
+    }
+}
+
+fn tick_hit_stun(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut HitStun, Option<&mut EnemySpriteAnimationStates>)>,
+) {
+    for (entity, mut hit_stun, enemy_animation) in &mut query {
+        hit_stun.timer.tick(time.delta());
+        if hit_stun.timer.finished() {
+            commands.entity(entity).remove::<HitStun>();
+            if let Some(mut animation) = enemy_animation {
+                animation.current_state = EnemyAnimationStates::IDLE;
+                animation.changed = true;
+            }
+        }
+    }
+}
+
+fn tick_knockback(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Knockback)>,
+) {
+    for (entity, mut knockback) in &mut query {
+        knockback.timer.tick(time.delta());
+        if knockback.timer.finished() {
+            commands.entity(entity).remove::<Knockback>();
+        }
+    }
+}
+
+fn tick_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invulnerable, &mut Visibility)>,
+) {
+    for (entity, mut invulnerable, mut visibility) in &mut query {
+        invulnerable.timer.tick(time.delta());
+        invulnerable.flash_timer.tick(time.delta());
+
+        if invulnerable.timer.finished() {
+            *visibility = Visibility::Inherited;
+            commands.entity(entity).remove::<Invulnerable>();
+        } else if invulnerable.flash_timer.just_finished() {
+            *visibility = match *visibility {
+                Visibility::Hidden => Visibility::Inherited,
+                _ => Visibility::Hidden,
+            };
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use bevy::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+/// Armor needed to halve incoming physical damage.
+const ARMOR_HALVING_POINT: f32 = 50.0;
+const DEFAULT_CRIT_MULTIPLIER: f32 = 1.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum DamageType {
+    #[default]
+    Physical,
+    Fire,
+    Ice,
+    Poison,
+}
+
+impl DamageType {
+    /// Colour of the damage numbers for this type.
+    pub fn color(&self) -> Color {
+        match self {
+            DamageType::Physical => Color::WHITE,
+            DamageType::Fire => Color::rgb(1.0, 0.55, 0.2),
+            DamageType::Ice => Color::rgb(0.55, 0.85, 1.0),
+            DamageType::Poison => Color::rgb(0.55, 1.0, 0.4),
+        }
+    }
+}
+
+/// Raw damage as dealt by an attack, before the target's defences are applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Damage {
+    pub amount: f32,
+    pub damage_type: DamageType,
+    /// Chance from 0.0 to 1.0 that the hit is critical.
+    pub crit_chance: f32,
+    pub crit_multiplier: f32,
+}
+
+impl Damage {
+    pub fn new(amount: f32, damage_type: DamageType) -> Self {
+        Damage {
+            amount,
+            damage_type,
+            crit_chance: 0.0,
+            crit_multiplier: DEFAULT_CRIT_MULTIPLIER,
+        }
+    }
+
+    pub fn with_crit(mut self, chance: f32, multiplier: f32) -> Self {
+        self.crit_chance = chance;
+        self.crit_multiplier = multiplier;
+        self
+    }
+}
+
+/// Fraction of each damage type an entity ignores. Negative values are weaknesses,
+/// so -0.5 means the entity takes 50% extra damage of that type.
+#[derive(Component, Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct Resistances {
+    pub physical: f32,
+    pub fire: f32,
+    pub ice: f32,
+    pub poison: f32,
+}
+
+impl Resistances {
+    pub fn get(&self, damage_type: DamageType) -> f32 {
+        match damage_type {
+            DamageType::Physical => self.physical,
+            DamageType::Fire => self.fire,
+            DamageType::Ice => self.ice,
+            DamageType::Poison => self.poison,
+        }
+    }
+}
+
+/// Flat defence against physical damage, with diminishing returns.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct Armor(pub f32);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DamageResult {
+    pub amount: f32,
+    pub damage_type: DamageType,
+    pub critical: bool,
+}
+
+/// Resolves a hit against a target's defences. Every source of damage goes through here so
+/// spells, melee, status effects and traps are balanced the same way.
+pub fn calculate_damage(
+    damage: &Damage,
+    resistances: Option<&Resistances>,
+    armor: Option<&Armor>,
+    rng: &mut impl Rng,
+) -> DamageResult {
+    let critical = damage.crit_chance > 0.0 && rng.gen::<f32>() < damage.crit_chance;
+    let mut amount = damage.amount;
+    if critical {
+        amount *= damage.crit_multiplier;
+    }
+
+    if let Some(resistances) = resistances {
+        amount *= 1.0 - resistances.get(damage.damage_type).clamp(-1.0, 1.0);
+    }
+
+    if damage.damage_type == DamageType::Physical {
+        if let Some(armor) = armor {
+            amount *= ARMOR_HALVING_POINT / (ARMOR_HALVING_POINT + armor.0.max(0.0));
+        }
+    }
+
+    DamageResult {
+        amount: amount.max(0.0),
+        damage_type: damage.damage_type,
+        critical,
+    }
+}
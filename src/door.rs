@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    collision::Faction,
+    interaction::{InteractAction, InteractEvent, Interactable},
+    inventory::Inventory,
+    level::key_field,
+    navigation::NavigationObstacle,
+    AppState, SCALE,
+};
+
+const DOOR_CLOSED_SPRITE_PATH: &str = "./textures/rpg/doors_leaf_closed.png";
+const DOOR_OPEN_SPRITE_PATH: &str = "./textures/rpg/doors_leaf_open.png";
+
+pub struct DoorPlugin;
+
+impl Plugin for DoorPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_ldtk_entity::<DoorBundle>("Door");
+        app.add_systems(Update, (
+            setup_doors,
+            open_doors,
+        ).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+/// A door that blocks the way until opened, placed as "Door" in the LDtk level and resized to fill the doorway.
+/// Doors with a "key" field are locked and use up that key when opened.
+#[derive(Clone, PartialEq, Debug, Default, Component)]
+pub struct Door {
+    pub key: Option<String>,
+    pub open: bool,
+    /// In level pixels.
+    pub size: Vec2,
+}
+
+impl From<&EntityInstance> for Door {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Door {
+            key: key_field(entity_instance),
+            open: entity_instance.get_bool_field("open").copied().unwrap_or(false),
+            size: Vec2::new(entity_instance.width as f32, entity_instance.height as f32),
+        }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct DoorBundle {
+    #[from_entity_instance]
+    door: Door,
+}
+
+fn door_sprite_path(open: bool) -> &'static str {
+    if open { DOOR_OPEN_SPRITE_PATH } else { DOOR_CLOSED_SPRITE_PATH }
+}
+
+fn setup_doors(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    door_query: Query<(Entity, &Door), Added<Door>>,
+) {
+    for (entity, door) in &door_query {
+        // Sized in level pixels, the level transform scales it up like the walls
+        commands.entity(entity).insert((
+            Sprite {
+                custom_size: Some(door.size),
+                ..Default::default()
+            },
+            asset_server.load::<Image>(door_sprite_path(door.open)),
+            RigidBody::Fixed,
+            Collider::cuboid(door.size.x / 2.0, door.size.y / 2.0),
+            Faction::Wall,
+            Faction::Wall.collision_groups(),
+            NavigationObstacle::new(door.size * SCALE),
+            Interactable {
+                enabled: !door.open,
+                ..Interactable::new(InteractAction::OpenDoor, if door.key.is_some() { "Unlock" } else { "Open" })
+            },
+        ));
+        if door.open {
+            commands.entity(entity).insert(ColliderDisabled);
+        }
+    }
+}
+
+fn open_doors(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut inventory: ResMut<Inventory>,
+    mut interact_events: EventReader<InteractEvent>,
+    mut door_query: Query<(&mut Door, &mut Interactable, &mut Handle<Image>)>,
+) {
+    for event in interact_events.read() {
+        let Ok((mut door, mut interactable, mut texture)) = door_query.get_mut(event.target) else {
+            continue;
+        };
+        if door.open {
+            continue;
+        }
+        if !inventory.use_key(door.key.as_deref()) {
+            println!("Door is locked, it needs {:?}", door.key);
+            continue;
+        }
+
+        println!("Opening door");
+        door.open = true;
+        door.key = None;
+        interactable.enabled = false;
+        *texture = asset_server.load(door_sprite_path(true));
+        commands.entity(event.target).insert(ColliderDisabled);
+    }
+}
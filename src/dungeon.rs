@@ -0,0 +1,444 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::ldtk::LdtkJson;
+use bevy_ecs_ldtk::prelude::*;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::{
+    game::OnGameScreen,
+    level::{merge_wall_rects, spawn_wall_colliders, ChestSpawnPoint, EnemySpawnPoint},
+    navigation::{NavigationGrid, NavigationGrids},
+    rng::{GameRng, RngStream},
+    transition::{update_level_transition, LevelEntrance, LevelExit},
+    AppState, SCALE,
+};
+
+const ROOM_TEMPLATES_PATH: &str = "./assets/level/dungeon_rooms.ldtk";
+const FLOOR_SPRITE_PATH: &str = "./textures/rpg/floor_1.png";
+const WALL_SPRITE_PATH: &str = "./textures/rpg/wall_mid.png";
+/// Selecting this level identifier generates a new dungeon floor instead of loading an LDtk level.
+pub const DUNGEON_LEVEL: &str = "Dungeon";
+/// Far away from the LDtk world so generated floors never overlap hand-made levels.
+const DUNGEON_ORIGIN: Vec2 = Vec2::new(0.0, -20000.0);
+const GRID_SIZE: i32 = 16;
+/// Every room is centred in a square of this many cells, so rooms can be up to two cells smaller.
+const ROOM_CELL_SIZE: i32 = 16;
+const MIN_ROOMS: usize = 5;
+const MAX_ROOMS: usize = 8;
+/// Corridors are three cells wide.
+const CORRIDOR_HALF_WIDTH: i32 = 1;
+
+pub struct DungeonPlugin;
+
+impl Plugin for DungeonPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RoomTemplates::default());
+        app.insert_resource(DungeonRun::default());
+        app.add_systems(Startup, load_room_templates);
+        app.add_systems(OnEnter(AppState::InGame), roll_dungeon_seed);
+        app.add_systems(OnExit(AppState::InGame), reset_dungeon);
+        // Reacts to the level selection in the same frame the transition changes it
+        app.add_systems(Update, update_dungeon.after(update_level_transition).run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RoomRole {
+    /// Where the player arrives, prefixed "Start_" in the templates file.
+    Start,
+    /// Prefixed "Room_".
+    Room,
+    /// Furthest from the start, holds the exit to the next floor. Prefixed "End_".
+    End,
+}
+
+#[derive(Clone, Debug)]
+pub enum DungeonEntityKind {
+    Enemy { archetype: String },
+    Chest { loot_table: String },
+    Entrance { id: String },
+    Exit { target_level: String, target_entrance: String },
+}
+
+/// An entity to place in the dungeon, at a cell counted from the top-left like LDtk does.
+#[derive(Clone, Debug)]
+pub struct DungeonEntity {
+    pub kind: DungeonEntityKind,
+    pub cell: IVec2,
+}
+
+/// A hand-authored room, one level of `assets/level/dungeon_rooms.ldtk`.
+#[derive(Clone, Debug)]
+pub struct RoomTemplate {
+    pub identifier: String,
+    pub role: RoomRole,
+    pub width: i32,
+    pub height: i32,
+    /// The "Collisions" IntGrid layer, 1 for walls.
+    pub int_grid: Vec<i32>,
+    pub entities: Vec<DungeonEntity>,
+}
+
+impl RoomTemplate {
+    fn from_level(level: &bevy_ecs_ldtk::ldtk::Level) -> Option<Self> {
+        let role = if level.identifier.starts_with("Start_") {
+            RoomRole::Start
+        } else if level.identifier.starts_with("End_") {
+            RoomRole::End
+        } else {
+            RoomRole::Room
+        };
+        let layers = level.layer_instances.as_ref()?;
+        let collisions = layers.iter().find(|layer| layer.identifier == "Collisions")?;
+
+        let mut entities = Vec::new();
+        for layer in layers {
+            for entity_instance in &layer.entity_instances {
+                let field = |name: &str| entity_instance.get_string_field(name).cloned().unwrap_or_default();
+                let kind = match entity_instance.identifier.as_str() {
+                    "Enemy" => DungeonEntityKind::Enemy { archetype: field("archetype") },
+                    "Chest" => DungeonEntityKind::Chest { loot_table: field("loot_table") },
+                    "Entrance" => DungeonEntityKind::Entrance { id: field("id") },
+                    "Exit" => DungeonEntityKind::Exit { target_level: field("target_level"), target_entrance: field("target_entrance") },
+                    _ => continue,
+                };
+                entities.push(DungeonEntity { kind, cell: entity_instance.grid });
+            }
+        }
+
+        Some(RoomTemplate {
+            identifier: level.identifier.clone(),
+            role,
+            width: collisions.c_wid,
+            height: collisions.c_hei,
+            int_grid: collisions.int_grid_csv.clone(),
+            entities,
+        })
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct RoomTemplates {
+    pub templates: Vec<RoomTemplate>,
+}
+
+impl RoomTemplates {
+    fn with_role(&self, role: RoomRole) -> Vec<&RoomTemplate> {
+        self.templates.iter().filter(|template| template.role == role).collect()
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DungeonTile {
+    /// Outside every room and corridor.
+    Empty,
+    Floor,
+    Wall,
+}
+
+/// A generated floor, laid out top-left first like an LDtk level.
+#[derive(Clone, Debug)]
+pub struct DungeonLayout {
+    pub width: i32,
+    pub height: i32,
+    pub tiles: Vec<DungeonTile>,
+    pub entities: Vec<DungeonEntity>,
+}
+
+impl DungeonLayout {
+    /// Stitches room templates into a tree of rooms joined by corridors.
+    /// The same templates and seed always give the same layout.
+    pub fn generate(templates: &RoomTemplates, seed: u64) -> Option<Self> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let start_templates = templates.with_role(RoomRole::Start);
+        let room_templates = templates.with_role(RoomRole::Room);
+        let end_templates = templates.with_role(RoomRole::End);
+        if start_templates.is_empty() || room_templates.is_empty() || end_templates.is_empty() {
+            return None;
+        }
+
+        // Grow rooms on a lattice, each new room next to a random existing one, so they form a tree
+        let room_count = rng.gen_range(MIN_ROOMS..=MAX_ROOMS);
+        let directions = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y];
+        let mut cells = vec![IVec2::ZERO];
+        let mut connections: Vec<(usize, usize)> = Vec::new();
+        for _ in 0..room_count * 20 {
+            if cells.len() >= room_count {
+                break;
+            }
+            let from = rng.gen_range(0..cells.len());
+            let cell = cells[from] + *directions.choose(&mut rng)?;
+            if cells.contains(&cell) {
+                continue;
+            }
+            connections.push((from, cells.len()));
+            cells.push(cell);
+        }
+
+        // The end room is the one the most rooms away from the start.
+        // Rooms are only ever connected to earlier rooms, so depths can be filled in order.
+        let mut depth = vec![0; cells.len()];
+        for &(from, to) in &connections {
+            depth[to] = depth[from] + 1;
+        }
+        let end = (1..cells.len()).max_by_key(|&room| depth[room])?;
+
+        let min = cells.iter().fold(IVec2::MAX, |min, &cell| min.min(cell));
+        let max = cells.iter().fold(IVec2::MIN, |max, &cell| max.max(cell));
+        let size = (max - min + IVec2::ONE) * ROOM_CELL_SIZE;
+        let mut layout = DungeonLayout {
+            width: size.x,
+            height: size.y,
+            tiles: vec![DungeonTile::Empty; (size.x * size.y) as usize],
+            entities: Vec::new(),
+        };
+
+        for (room, &cell) in cells.iter().enumerate() {
+            let template = match room {
+                0 => start_templates.choose(&mut rng)?,
+                room if room == end => end_templates.choose(&mut rng)?,
+                _ => room_templates.choose(&mut rng)?,
+            };
+            let offset = (cell - min) * ROOM_CELL_SIZE + (IVec2::splat(ROOM_CELL_SIZE) - IVec2::new(template.width, template.height)) / 2;
+            layout.stamp_room(template, offset);
+        }
+
+        // Corridors run between room centres and knock through the room walls on the way
+        let room_centre = |cell: IVec2| (cell - min) * ROOM_CELL_SIZE + IVec2::splat(ROOM_CELL_SIZE / 2);
+        for &(from, to) in &connections {
+            layout.carve_corridor(room_centre(cells[from]), room_centre(cells[to]));
+        }
+
+        Some(layout)
+    }
+
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        if cell.x < 0 || cell.y < 0 || cell.x >= self.width || cell.y >= self.height {
+            return None;
+        }
+        Some((cell.y * self.width + cell.x) as usize)
+    }
+
+    pub fn tile(&self, cell: IVec2) -> DungeonTile {
+        self.index(cell).map_or(DungeonTile::Empty, |index| self.tiles[index])
+    }
+
+    fn set_tile(&mut self, cell: IVec2, tile: DungeonTile) {
+        if let Some(index) = self.index(cell) {
+            self.tiles[index] = tile;
+        }
+    }
+
+    fn stamp_room(&mut self, template: &RoomTemplate, offset: IVec2) {
+        for (index, &value) in template.int_grid.iter().enumerate() {
+            let cell = offset + IVec2::new(index as i32 % template.width, index as i32 / template.width);
+            self.set_tile(cell, if value == 1 { DungeonTile::Wall } else { DungeonTile::Floor });
+        }
+        for entity in &template.entities {
+            self.entities.push(DungeonEntity { kind: entity.kind.clone(), cell: offset + entity.cell });
+        }
+    }
+
+    /// Straight corridor between two cells on the same row or column.
+    fn carve_corridor(&mut self, from: IVec2, to: IVec2) {
+        let step = (to - from).signum();
+        let side = IVec2::new(step.y, step.x);
+        let mut cell = from;
+        loop {
+            for offset in -CORRIDOR_HALF_WIDTH..=CORRIDOR_HALF_WIDTH {
+                self.set_tile(cell + side * offset, DungeonTile::Floor);
+            }
+            for offset in [-CORRIDOR_HALF_WIDTH - 1, CORRIDOR_HALF_WIDTH + 1] {
+                if self.tile(cell + side * offset) == DungeonTile::Empty {
+                    self.set_tile(cell + side * offset, DungeonTile::Wall);
+                }
+            }
+            if cell == to {
+                break;
+            }
+            cell += step;
+        }
+    }
+
+    /// The layout as an LDtk "Collisions" IntGrid layer, 1 for walls.
+    pub fn int_grid(&self) -> Vec<i32> {
+        self.tiles.iter().map(|&tile| if tile == DungeonTile::Wall { 1 } else { 0 }).collect()
+    }
+}
+
+/// Wall cells of an LDtk IntGrid layer as the bottom-up `GridCoords` bevy_ecs_ldtk gives `Wall` tiles.
+pub fn walls_from_int_grid(int_grid: &[i32], width: i32, height: i32) -> HashSet<GridCoords> {
+    int_grid.iter().enumerate()
+        .filter(|(_, value)| **value == 1)
+        .map(|(index, _)| GridCoords {
+            x: index as i32 % width,
+            y: height - 1 - index as i32 / width,
+        })
+        .collect()
+}
+
+/// Seed of the current run and the floor the player is on.
+#[derive(Resource, Default, Debug)]
+pub struct DungeonRun {
+    pub seed: u64,
+    pub floor: u32,
+    root: Option<Entity>,
+}
+
+/// Root of a generated floor, it stands in for the level entity.
+#[derive(Component)]
+pub struct Dungeon;
+
+/// The floor a generated entrance belongs to. Every floor reuses the same entrance id,
+/// so transitions match on this too and never place the player on the floor being replaced.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct DungeonFloor(pub u32);
+
+fn load_room_templates(
+    mut room_templates: ResMut<RoomTemplates>,
+) {
+    let mut file = File::open(ROOM_TEMPLATES_PATH).expect("Failed to open dungeon room templates file");
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).expect("Failed to read dungeon room templates file");
+    let project: LdtkJson = serde_json::from_str(&contents).expect("Failed to parse dungeon room templates");
+
+    for level in &project.levels {
+        if let Some(template) = RoomTemplate::from_level(level) {
+            println!("Loaded room template {}", template.identifier);
+            room_templates.templates.push(template);
+        }
+    }
+}
+
+fn roll_dungeon_seed(
+    mut dungeon_run: ResMut<DungeonRun>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    dungeon_run.seed = game_rng.stream(RngStream::Dungeon).gen();
+    println!("Dungeon seed {}", dungeon_run.seed);
+}
+
+fn reset_dungeon(
+    mut dungeon_run: ResMut<DungeonRun>,
+    mut navigation_grids: ResMut<NavigationGrids>,
+) {
+    // The root itself is an OnGameScreen entity and is despawned with the rest of the game
+    if let Some(root) = dungeon_run.root.take() {
+        navigation_grids.grids.remove(&root);
+    }
+    dungeon_run.floor = 0;
+}
+
+fn update_dungeon(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    level_selection: Res<LevelSelection>,
+    room_templates: Res<RoomTemplates>,
+    mut dungeon_run: ResMut<DungeonRun>,
+    mut navigation_grids: ResMut<NavigationGrids>,
+) {
+    if !level_selection.is_changed() {
+        return;
+    }
+    if let Some(root) = dungeon_run.root.take() {
+        commands.entity(root).despawn_recursive();
+        navigation_grids.grids.remove(&root);
+    }
+    if !matches!(&*level_selection, LevelSelection::Identifier(identifier) if identifier == DUNGEON_LEVEL) {
+        dungeon_run.floor = 0;
+        return;
+    }
+
+    // Exits in the end room select the dungeon again, which moves on to the next floor
+    dungeon_run.floor += 1;
+    let seed = dungeon_run.seed.wrapping_add(dungeon_run.floor as u64);
+    let Some(layout) = DungeonLayout::generate(&room_templates, seed) else {
+        println!("Not enough room templates to generate a dungeon");
+        return;
+    };
+    println!("Generated dungeon floor {} ({}x{}) from seed {}", dungeon_run.floor, layout.width, layout.height, seed);
+    dungeon_run.root = Some(spawn_dungeon(&mut commands, &asset_server, &mut navigation_grids, &layout, dungeon_run.floor));
+}
+
+fn spawn_dungeon(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    navigation_grids: &mut NavigationGrids,
+    layout: &DungeonLayout,
+    floor: u32,
+) -> Entity {
+    let walls = walls_from_int_grid(&layout.int_grid(), layout.width, layout.height);
+    let wall_rects = merge_wall_rects(&walls, layout.width, layout.height);
+    let floor_texture: Handle<Image> = asset_server.load(FLOOR_SPRITE_PATH);
+    let wall_texture: Handle<Image> = asset_server.load(WALL_SPRITE_PATH);
+    // Level pixel position of a cell counted from the top-left, like the children of an LDtk level
+    let cell_centre = |cell: IVec2| Vec2::new(
+        (cell.x as f32 + 0.5) * GRID_SIZE as f32,
+        ((layout.height - 1 - cell.y) as f32 + 0.5) * GRID_SIZE as f32,
+    );
+
+    let root = commands.spawn((
+        SpatialBundle {
+            // Scaled like the LDtk world so everything below is in level pixels
+            transform: Transform {
+                translation: DUNGEON_ORIGIN.extend(-1.0),
+                scale: Vec3::new(SCALE, SCALE, 1.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Dungeon,
+        Name::new("Dungeon"),
+        OnGameScreen,
+    ))
+    .with_children(|level| {
+        for y in 0..layout.height {
+            for x in 0..layout.width {
+                let cell = IVec2::new(x, y);
+                let (texture, z) = match layout.tile(cell) {
+                    DungeonTile::Empty => continue,
+                    DungeonTile::Floor => (floor_texture.clone(), 0.0),
+                    DungeonTile::Wall => (wall_texture.clone(), 0.1),
+                };
+                level.spawn(SpriteBundle {
+                    texture,
+                    transform: Transform::from_translation(cell_centre(cell).extend(z)),
+                    ..Default::default()
+                });
+            }
+        }
+
+        spawn_wall_colliders(level, wall_rects, GRID_SIZE);
+
+        // Markers are picked up by the same systems as the ones placed in LDtk levels
+        for entity in &layout.entities {
+            let spatial = SpatialBundle::from_transform(Transform::from_translation(cell_centre(entity.cell).extend(1.0)));
+            match &entity.kind {
+                DungeonEntityKind::Enemy { archetype } => {
+                    level.spawn((spatial, EnemySpawnPoint { archetype: archetype.clone() }));
+                },
+                DungeonEntityKind::Chest { loot_table } => {
+                    level.spawn((spatial, ChestSpawnPoint { loot_table: loot_table.clone(), key: None }));
+                },
+                DungeonEntityKind::Entrance { id } => {
+                    level.spawn((spatial, LevelEntrance { id: id.clone() }, DungeonFloor(floor)));
+                },
+                DungeonEntityKind::Exit { target_level, target_entrance } => {
+                    level.spawn((spatial, LevelExit { target_level: target_level.clone(), target_entrance: target_entrance.clone() }));
+                },
+            }
+        }
+    })
+    .id();
+
+    let mut grid = NavigationGrid::new(layout.width, layout.height, DUNGEON_ORIGIN, GRID_SIZE as f32 * SCALE);
+    for wall in &walls {
+        grid.add_wall(IVec2::new(wall.x, wall.y));
+    }
+    navigation_grids.grids.insert(root, grid);
+
+    root
+}
@@ -1,26 +1,23 @@
-use std::f32::consts::PI;
 use rand::prelude::*;
 
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
 
-use crate::{game::AnimationTimer, player::{AnimationIndices, ControllablePlayer, Facing, SpriteFacing}, spritesheet::{get_enemy_sprite_animation_states, get_sprite_atlas_layout, get_sprite_texture_handle, SpriteCollection, TextureAtlases, CHORT_IDLE, CHORT_RUN, LIZARD_M_HIT}, AppState, FONT_PATH, SCALE,};
+use crate::{archetype::ArchetypeId, behaviour::{BehaviourState, EnemyBehaviour}, combat::{HitEvent, HitStun}, damage::{Damage, DamageResult, DamageType}, game::AnimationTimer, magic::{spawn_enemy_projectile, FireBallSpriteAtlas, IceSpikeSpriteAtlas, Spells}, status_effect::StatusEffects, player::{AnimationIndices, ControllablePlayer, Velocity as PlayerVelocity}, AppState, FONT_PATH,};
 
 pub struct EnemyPlugin;
 
+/// How far in front of a caster its projectiles appear, so they do not start inside its sprite.
+const PROJECTILE_SPAWN_OFFSET: f32 = 40.0;
+
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(AppState::InGame), setup);
-        app.add_systems(Update, (animate_sprite, enemy_movement, test_damage_number, update_damage_numbers).run_if(in_state(AppState::InGame)));
+        app.add_event::<EnemyDeathEvent>();
+        app.add_systems(Update, (animate_sprite, enemy_contact_damage, enemy_attack, test_damage_number, update_damage_numbers, despawn_dead_enemies).run_if(in_state(AppState::InGame)));
     }
 }
 
-pub struct AnimatedSprite {
-    pub texture: Handle<Image>,
-    pub atlas_layout: Handle<TextureAtlasLayout>,
-    pub animation_states: Vec<(EnemyAnimationStates, Vec<usize>)>,
-}
-
 #[derive(Debug, PartialEq)]
 pub enum EnemyAnimationStates {
     IDLE,
@@ -31,6 +28,79 @@ pub enum EnemyAnimationStates {
 #[derive(Component)]
 pub struct Enemy;
 
+/// Sent for every enemy killed, just before it is despawned.
+#[derive(Event, Debug, Clone)]
+pub struct EnemyDeathEvent {
+    pub entity: Entity,
+    pub position: Vec2,
+    pub archetype: Option<String>,
+}
+
+#[derive(Component, Debug)]
+pub struct EnemyHealth {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl EnemyHealth {
+    pub fn new(max: f32) -> Self {
+        EnemyHealth { current: max, max }
+    }
+}
+
+/// Damage dealt to the player simply by touching the enemy.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct ContactDamage {
+    pub damage: f32,
+    pub knockback: f32,
+    pub radius: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub enum AttackPattern {
+    /// Dash toward the player after the windup, relying on contact damage to land the hit.
+    Lunge { speed: f32 },
+    /// Strike everything within `radius` once the windup completes.
+    Slam { radius: f32 },
+    /// Cast a spell projectile at where the player is heading once the windup completes.
+    Projectile { spell: Spells, speed: f32 },
+}
+
+impl AttackPattern {
+    /// Colour the enemy is tinted towards while winding up.
+    fn telegraph_color(&self) -> Color {
+        match self {
+            AttackPattern::Projectile { spell, .. } => spell.damage_type().color(),
+            _ => Color::rgb(1.0, 0.4, 0.4),
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct EnemyAttack {
+    pub pattern: AttackPattern,
+    pub range: f32,
+    pub damage: f32,
+    pub knockback: f32,
+    pub windup: Timer,
+    pub cooldown: Timer,
+    pub winding_up: bool,
+}
+
+impl EnemyAttack {
+    pub fn new(pattern: AttackPattern, range: f32, damage: f32, knockback: f32, windup: f32, cooldown: f32) -> Self {
+        EnemyAttack {
+            pattern,
+            range,
+            damage,
+            knockback,
+            windup: Timer::from_seconds(windup, TimerMode::Once),
+            cooldown: Timer::from_seconds(cooldown, TimerMode::Once),
+            winding_up: false,
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct EnemySpriteAnimationStates {
     pub current_state: EnemyAnimationStates,
@@ -47,6 +117,7 @@ struct DamageNumbers {
 
 impl DamageNumbers {
     fn new(value: i32) -> Self {
+        // Cosmetic only, so it stays off the seeded gameplay streams
         let mut rng = rand::thread_rng();
         let angle = rng.gen_range(0.0..std::f32::consts::TAU);
         let direction = Vec3::new(angle.cos(), angle.sin(), 0.0);
@@ -59,21 +130,28 @@ impl DamageNumbers {
     }
 }
 
-fn spawn_damage_number(
-    mut commands: Commands,
-    asset_server: ResMut<AssetServer>,
-    value: i32,
+/// Critical hits are drawn larger with an exclamation mark, coloured by damage type.
+pub fn spawn_damage_number(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    result: &DamageResult,
     position: Vec3,
 ) {
     println!("Spawned damage number");
+    let value = result.amount.ceil() as i32;
+    let (label, font_size) = if result.critical {
+        (format!("{}!", value), 42.0)
+    } else {
+        (value.to_string(), 30.0)
+    };
     commands.spawn((
         Text2dBundle {
             text: Text::from_section(
-                value.to_string(),
+                label,
                 TextStyle {
                     font: asset_server.load(FONT_PATH),
-                    font_size: 30.0,
-                    color: Color::WHITE.into(),
+                    font_size,
+                    color: result.damage_type.color(),
                 },
             ),
             transform: Transform::from_translation(position),
@@ -102,208 +180,191 @@ fn update_damage_numbers(
 }
 
 fn test_damage_number(
-    commands: Commands,
-    asset_server: ResMut<AssetServer>,
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
 ) {
     if keyboard_input.just_pressed(KeyCode::KeyI) {
-        let value = 10;
+        let result = DamageResult { amount: 10.0, damage_type: DamageType::Physical, critical: false };
         let position = Vec3::new(1500.0, 1500.0, 7.0);
     
-        spawn_damage_number(commands, asset_server, value, position)
+        spawn_damage_number(&mut commands, &asset_server, &result, position)
     }
 }
 
-fn setup(
-    mut commands: Commands,
-    texture_atlas: Res<TextureAtlases>,
-    sprite_collection: Res<SpriteCollection>,
-) {
-    let requested_idle_sprite = CHORT_IDLE.to_owned();
-    let requested_running_sprite = CHORT_RUN.to_owned();
-    let requested_hit_sprite = LIZARD_M_HIT.to_owned();
-
-    let animated_sprite_texture = get_sprite_texture_handle(
-        requested_idle_sprite.clone(), 
-        &texture_atlas, 
-        &sprite_collection
-    ).expect("Could not find sprite texture handle");
-
-    let animated_sprite_atlas_layout = get_sprite_atlas_layout(
-        requested_idle_sprite.clone(), 
-        &texture_atlas, 
-        &sprite_collection
-    ).expect("Could not find sprite texture atlas layout");
-
-
-    let mut animated_sprite = AnimatedSprite {
-        texture: animated_sprite_texture,
-        atlas_layout: animated_sprite_atlas_layout,
-        animation_states: vec![],
-    };
-
-
-    animated_sprite.animation_states.push(
-        get_enemy_sprite_animation_states(
-            EnemyAnimationStates::IDLE,
-            requested_idle_sprite,
-            &sprite_collection
-        )
-    );
-
-    animated_sprite.animation_states.push(
-        get_enemy_sprite_animation_states(
-            EnemyAnimationStates::RUNNING,
-            requested_running_sprite,
-            &sprite_collection
-        )
-    );
-
-    animated_sprite.animation_states.push(
-        get_enemy_sprite_animation_states(
-            EnemyAnimationStates::HIT,
-            requested_hit_sprite,
-            &sprite_collection
-        )
-    );
-
-    let mut default_sprite_index_first: usize = 0;
-    let mut default_sprite_index_last: usize = 0;
-    for (animation_state, indices) in &animated_sprite.animation_states {
-        if *animation_state == EnemyAnimationStates::IDLE {
-            default_sprite_index_first = indices[0];
-            default_sprite_index_last = *indices.last().unwrap();
-        }
-    }
-
-    let animation_indices = AnimationIndices {
-        first: default_sprite_index_first,
-        last: default_sprite_index_last
-    };
-
-    let sprite_animation_states = EnemySpriteAnimationStates {
-        current_state: EnemyAnimationStates::IDLE,
-        available_states: animated_sprite.animation_states,
-        changed: false,
-    };
-
-    let bot_entity = commands.spawn((
-        SpriteSheetBundle {
-            sprite: Sprite {
-                flip_x: false,
-                ..Default::default()
-            },
-            texture: animated_sprite.texture,
-            atlas: TextureAtlas {
-                layout: animated_sprite.atlas_layout,
-                index: animation_indices.first,
-            },
-            transform: Transform {
-                translation: Vec3 { x: 1450.0, y: 1450.0, z: 5.0 },
-                rotation: Quat::default(),
-                scale: Vec3 { x: SCALE/1.2, y: SCALE/1.2, z: 1.0 },
-            },
-            ..Default::default()
-        },
-        SpriteFacing { facing: Facing::RIGHT },
-        sprite_animation_states,
-        animation_indices.clone(),
-        AnimationTimer(Timer::from_seconds(0.1, TimerMode::Repeating)),
-        Enemy{},
-        RigidBody::Dynamic,
-        LockedAxes::ROTATION_LOCKED,
-        CollisionGroups::new(Group::from_bits(0b01).unwrap(), Group::from_bits(0b01).unwrap()),
-        ColliderMassProperties::Density(2.0),
-        Damping {
-            linear_damping: 2.0,
-            ..Default::default()
-        },
-        // TransformBundle::from(Transform { translation: Vec3::new(0.0, -4.0, 0.0), ..Default::default()}),
-        Collider::cuboid(10.0, 10.0),
-        ActiveEvents::COLLISION_EVENTS,
-        Name::new("Enemy"),
-    )).id();
-    
-}
-
 fn animate_sprite(
     time: Res<Time>,
-    mut query: Query<(&mut AnimationIndices, &mut AnimationTimer, &mut TextureAtlas), With<Enemy>>,
-    mut animation_state_query: Query<&mut EnemySpriteAnimationStates, With<Enemy>>,
+    mut query: Query<(&mut AnimationIndices, &mut AnimationTimer, &mut TextureAtlas, &mut EnemySpriteAnimationStates), With<Enemy>>,
 ) {
-    if let Ok(mut current_animation_state) = animation_state_query.get_single_mut() {
+    for (mut indices, mut timer, mut sprite, mut current_animation_state) in &mut query {
         if current_animation_state.changed {
-            let mut index_first: usize = 0;
-            let mut index_last: usize = 0;
             for state in &current_animation_state.available_states {
                 if state.0 == current_animation_state.current_state {
-                    index_first = *state.1.first().unwrap();
-                    index_last = *state.1.last().unwrap();
+                    indices.first = *state.1.first().unwrap();
+                    indices.last = *state.1.last().unwrap();
                 }
             }
-            for (mut indices, _timer, mut sprite) in &mut query {
-                indices.first = index_first;
-                indices.last = index_last;
-                sprite.index = index_first;
-            }
+            sprite.index = indices.first;
             current_animation_state.changed = false;
         }
-    
-        for (indices, mut timer, mut sprite) in &mut query {
-            timer.tick(time.delta());
-            if timer.just_finished() {
-                sprite.index = if sprite.index == indices.last {
-                    indices.first
-                } else {
-                    sprite.index + 1
-                };
-            }
+
+        timer.tick(time.delta());
+        if timer.just_finished() {
+            sprite.index = if sprite.index == indices.last {
+                indices.first
+            } else {
+                sprite.index + 1
+            };
         }
     }
 }
 
-fn enemy_movement(
+fn despawn_dead_enemies(
+    mut commands: Commands,
+    enemy_query: Query<(Entity, &EnemyHealth, &Transform, Option<&ArchetypeId>), With<Enemy>>,
+    mut death_events: EventWriter<EnemyDeathEvent>,
+) {
+    for (entity, health, transform, archetype) in &enemy_query {
+        if health.current <= 0.0 {
+            death_events.send(EnemyDeathEvent {
+                entity,
+                position: transform.translation.truncate(),
+                archetype: archetype.map(|archetype| archetype.0.clone()),
+            });
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn enemy_contact_damage(
+    enemy_query: Query<(&Transform, &ContactDamage), (With<Enemy>, Without<HitStun>)>,
+    player_query: Query<(Entity, &Transform), With<ControllablePlayer>>,
+    mut hit_events: EventWriter<HitEvent>,
+) {
+    let Ok((player_entity, player_transform)) = player_query.get_single() else {
+        return;
+    };
+
+    for (transform, contact_damage) in &enemy_query {
+        let distance = transform.translation.truncate().distance(player_transform.translation.truncate());
+        if distance <= contact_damage.radius {
+            hit_events.send(HitEvent {
+                target: player_entity,
+                source_position: transform.translation,
+                damage: Damage::new(contact_damage.damage, DamageType::Physical),
+                knockback: contact_damage.knockback,
+                stun: 0.2,
+            });
+        }
+    }
+}
+
+fn enemy_attack(
+    mut commands: Commands,
     time: Res<Time>,
-    mut enemy_query: Query<(&mut Transform, &mut EnemySpriteAnimationStates, &mut Sprite), With<Enemy>>,
-    player_query: Query<&Transform, (With<ControllablePlayer>, Without<Enemy>)>,
+    mut enemy_query: Query<(Entity, &Transform, &mut EnemyAttack, &mut Sprite, Option<&StatusEffects>, Option<&EnemyBehaviour>), (With<Enemy>, Without<HitStun>)>,
+    player_query: Query<(Entity, &Transform, Option<&PlayerVelocity>), With<ControllablePlayer>>,
+    mut hit_events: EventWriter<HitEvent>,
+    fireball_sprite: Res<FireBallSpriteAtlas>,
+    icespike_sprite: Res<IceSpikeSpriteAtlas>,
 ) {
-    let player_transform = player_query.get_single().unwrap();
-    let player_position = player_transform.translation;
-
-    for (mut transform, mut state, mut sprite) in &mut enemy_query {
-        let vector = player_position - transform.translation;
-        let angle = vector.y.atan2(vector.x);
-        let direction = player_position - transform.translation;
-        let distance = direction.length();
-
-        let radius = 200.0;
-        if radius > distance {
-            if state.current_state == EnemyAnimationStates::IDLE {
-                state.changed = true;
-            }
+    let Ok((player_entity, player_transform, player_velocity)) = player_query.get_single() else {
+        return;
+    };
 
-            if state.changed == true {
-                state.current_state = EnemyAnimationStates::RUNNING;
-            }
+    for (entity, transform, mut attack, mut sprite, status_effects, behaviour) in &mut enemy_query {
+        attack.cooldown.tick(time.delta());
+        let offset = player_transform.translation.truncate() - transform.translation.truncate();
 
-            if angle <= 0.0 && angle >= -PI/2.0 {
-                sprite.flip_x = false;
-            }
-            if angle <= -PI/2.0 && angle >= -PI {
-                sprite.flip_x = true;
-            }
-            if angle > 0.0 && angle <= PI/2.0 {
-                sprite.flip_x = false;
+        if !attack.winding_up {
+            let wants_to_attack = behaviour.map_or(true, |behaviour| behaviour.state == BehaviourState::Attack);
+            if wants_to_attack && attack.cooldown.finished() && offset.length() <= attack.range {
+                attack.winding_up = true;
+                attack.windup.reset();
             }
-            if angle > PI/2.0 && angle <= PI {
-                sprite.flip_x = true;
-            }
-            let movement = direction.normalize() * time.delta_seconds() * 80.0;
-            transform.translation += movement;
-        } 
-        else if state.current_state != EnemyAnimationStates::IDLE {
-            state.current_state = EnemyAnimationStates::IDLE;
-            state.changed = true;
+            continue;
+        }
+
+        // Telegraph the attack by tinting the enemy while winding up
+        attack.windup.tick(time.delta());
+        let telegraph = attack.pattern.telegraph_color().rgba_to_vec4();
+        let tint = telegraph.lerp(Vec4::ONE, attack.windup.fraction_remaining());
+        sprite.color = Color::rgba(tint.x, tint.y, tint.z, 1.0);
+        if !attack.windup.finished() {
+            continue;
+        }
+
+        attack.winding_up = false;
+        attack.cooldown.reset();
+        sprite.color = Color::WHITE;
+        if status_effects.is_some_and(|effects| effects.movement_multiplier() == 0.0) {
+            continue;
+        }
+
+        match attack.pattern {
+            AttackPattern::Lunge { speed } => {
+                commands.entity(entity).insert(Velocity::linear(offset.normalize_or_zero() * speed));
+            },
+            AttackPattern::Slam { radius } => {
+                if offset.length() <= radius {
+                    hit_events.send(HitEvent {
+                        target: player_entity,
+                        source_position: transform.translation,
+                        damage: Damage::new(attack.damage, DamageType::Physical),
+                        knockback: attack.knockback,
+                        stun: 0.3,
+                    });
+                }
+            },
+            AttackPattern::Projectile { spell, speed } => {
+                let origin = transform.translation.truncate();
+                let target_velocity = player_velocity.map_or(Vec2::ZERO, |velocity| velocity.0);
+                let aim = predict_intercept(origin, player_transform.translation.truncate(), target_velocity, speed);
+                let Some(direction) = (aim - origin).try_normalize() else {
+                    continue;
+                };
+                spawn_enemy_projectile(
+                    &mut commands,
+                    &fireball_sprite,
+                    &icespike_sprite,
+                    spell,
+                    origin + direction * PROJECTILE_SPAWN_OFFSET,
+                    direction,
+                    speed,
+                    Damage::new(attack.damage, spell.damage_type()),
+                    attack.knockback,
+                );
+            },
         }
     }
-}
\ No newline at end of file
+}
+
+/// Where to aim so a projectile at `speed` meets a target moving at constant velocity.
+/// Falls back to the target's current position when it is too fast to be intercepted.
+fn predict_intercept(origin: Vec2, target: Vec2, target_velocity: Vec2, speed: f32) -> Vec2 {
+    let offset = target - origin;
+    // Solve |offset + target_velocity * t| = speed * t for the earliest positive t
+    let a = target_velocity.length_squared() - speed * speed;
+    let b = 2.0 * offset.dot(target_velocity);
+    let c = offset.length_squared();
+
+    let time = if a.abs() < f32::EPSILON {
+        if b.abs() < f32::EPSILON { None } else { Some(-c / b) }
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            None
+        } else {
+            let root = discriminant.sqrt();
+            [(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+                .into_iter()
+                .filter(|time| *time > 0.0)
+                .reduce(f32::min)
+        }
+    };
+
+    match time.filter(|time| *time > 0.0) {
+        Some(time) => target + target_velocity * time,
+        None => target,
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{network::CurrentClientId, player::{ControllablePlayer, PlayerSpriteAtlas}, spritesheet::{get_sprite, SpriteCollection, TextureAtlases, ANGEL_IDLE, KNIGHT_M_IDLE}, AppState, PlayerCamera, PlayerPosition, FONT_PATH, PLAYER_SPRITE_PATH, SCALE};
+use crate::{mainmenu::despawn_screen, network::CurrentClientId, player::{ControllablePlayer, PlayerSpriteAtlas}, spritesheet::{get_sprite, SpriteCollection, TextureAtlases, ANGEL_IDLE, KNIGHT_M_IDLE}, AppState, PlayerCamera, PlayerPosition, FONT_PATH, PLAYER_SPRITE_PATH, SCALE};
 
 pub struct GamePlugin;
 
@@ -18,6 +18,7 @@ impl Plugin for GamePlugin {
         app.add_systems(Startup, setup_camera);
 
         app.add_systems(OnEnter(AppState::InGame), (setup, get_window));
+        app.add_systems(OnExit(AppState::InGame), despawn_screen::<OnGameScreen>);
     }
 }
 
@@ -47,6 +48,10 @@ pub struct Sword {
 #[derive(Component)]
 pub struct Equipment;
 
+/// Marks entities that belong to a running game and are despawned when leaving `AppState::InGame`.
+#[derive(Component)]
+pub struct OnGameScreen;
+
 #[derive(SystemSet, Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Connected;
 
@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+
+use crate::{
+    combat::{HitStun, Invulnerable, Knockback},
+    healthbar::{PlayerHealth, PlayerMana, PLAYER_MAX_HEALTH},
+    level::{PlayerStart, RespawnPoint},
+    mainmenu::despawn_screen,
+    navigation::NavigationGrids,
+    player::{ControllablePlayer, PlayerAnimationStates, PlayerDead, PlayerDeathEvent, PlayerSpriteAnimationStates},
+    AppState, FONT_PATH,
+};
+
+const DEATH_SEQUENCE_SECONDS: f32 = 1.5;
+const RESPAWN_INVULNERABILITY_SECONDS: f32 = 2.0;
+
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameOverState>();
+        app.insert_resource(DeathTimer(Timer::from_seconds(DEATH_SEQUENCE_SECONDS, TimerMode::Once)));
+        app.add_systems(Update, start_death_sequence.run_if(in_state(AppState::InGame)));
+        app.add_systems(Update, play_death_sequence.run_if(in_state(GameOverState::Dying)));
+        app.add_systems(OnEnter(GameOverState::Shown), game_over_setup);
+        app.add_systems(OnExit(GameOverState::Shown), despawn_screen::<OnGameOverScreen>);
+        app.add_systems(Update, game_over_action.run_if(in_state(GameOverState::Shown)));
+        app.add_systems(OnExit(AppState::InGame), reset_game_over_state);
+    }
+}
+
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Hash, States)]
+pub enum GameOverState {
+    #[default]
+    Disabled,
+    Dying,
+    Shown,
+}
+
+#[derive(Resource, Deref, DerefMut)]
+struct DeathTimer(Timer);
+
+#[derive(Component)]
+enum GameOverButtonAction {
+    Retry,
+    MainMenu,
+}
+
+#[derive(Component)]
+struct OnGameOverScreen;
+
+fn start_death_sequence(
+    mut death_events: EventReader<PlayerDeathEvent>,
+    mut death_timer: ResMut<DeathTimer>,
+    mut game_over_state: ResMut<NextState<GameOverState>>,
+    mut player_query: Query<&mut PlayerSpriteAnimationStates, With<ControllablePlayer>>,
+) {
+    for event in death_events.read() {
+        println!("Starting death sequence");
+        if let Ok(mut animation) = player_query.get_mut(event.player) {
+            animation.current_state = PlayerAnimationStates::HIT;
+        }
+        death_timer.reset();
+        game_over_state.set(GameOverState::Dying);
+    }
+}
+
+fn play_death_sequence(
+    time: Res<Time>,
+    mut death_timer: ResMut<DeathTimer>,
+    mut game_over_state: ResMut<NextState<GameOverState>>,
+    mut player_query: Query<(&mut Sprite, &mut PlayerSpriteAnimationStates), (With<ControllablePlayer>, With<PlayerDead>)>,
+) {
+    death_timer.tick(time.delta());
+
+    for (mut sprite, mut animation) in &mut player_query {
+        // Hold the hit animation while the player fades out
+        animation.current_state = PlayerAnimationStates::HIT;
+        sprite.color.set_a(death_timer.fraction_remaining());
+    }
+
+    if death_timer.finished() {
+        game_over_state.set(GameOverState::Shown);
+    }
+}
+
+fn game_over_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load(FONT_PATH);
+
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+            z_index: ZIndex::Global(10),
+            ..Default::default()
+        },
+        OnGameOverScreen,
+    )).with_children(|parent| {
+        parent.spawn(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        }).with_children(|parent| {
+            parent.spawn(
+                TextBundle::from_section(
+                    "GAME OVER",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 80.0,
+                        color: Color::rgb(0.8, 0.1, 0.1),
+                        ..Default::default()
+                    }
+                )
+            );
+
+            for (label, action) in [("Retry", GameOverButtonAction::Retry), ("Main Menu", GameOverButtonAction::MainMenu)] {
+                parent.spawn((
+                    ButtonBundle {
+                        background_color: Color::NONE.into(),
+                        style: Style {
+                            flex_direction: FlexDirection::Column,
+                            align_items: AlignItems::Center,
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    action,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(
+                        TextBundle::from_section(
+                            label,
+                            TextStyle {
+                                font: font.clone(),
+                                font_size: 40.0,
+                                color: Color::WHITE,
+                                ..Default::default()
+                            }
+                        )
+                    );
+                });
+            }
+        });
+    });
+}
+
+fn game_over_action(
+    mut commands: Commands,
+    interaction_query: Query<(&Interaction, &GameOverButtonAction), (Changed<Interaction>, With<Button>)>,
+    mut game_over_state: ResMut<NextState<GameOverState>>,
+    mut app_state: ResMut<NextState<AppState>>,
+    mut player_health: ResMut<PlayerHealth>,
+    mut player_mana: ResMut<PlayerMana>,
+    mut player_query: Query<(Entity, &mut Transform, &mut Sprite, &mut PlayerSpriteAnimationStates), With<ControllablePlayer>>,
+    respawn_point: Res<RespawnPoint>,
+    navigation_grids: Res<NavigationGrids>,
+    player_start_query: Query<&GlobalTransform, With<PlayerStart>>,
+) {
+    for (interaction, action) in &interaction_query {
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+
+        player_health.0 = PLAYER_MAX_HEALTH;
+        *player_mana = PlayerMana::default();
+
+        match action {
+            GameOverButtonAction::Retry => {
+                println!("Respawning player");
+                for (entity, mut transform, mut sprite, mut animation) in &mut player_query {
+                    // Before the player has been placed anywhere, fall back to the "Player" entity of the level they died in
+                    let spawn_position = respawn_point.0.or_else(|| {
+                        let grid = navigation_grids.grid_at(transform.translation.truncate())?;
+                        player_start_query.iter()
+                            .map(|start| start.translation().truncate())
+                            .find(|start| grid.contains(grid.world_to_cell(*start)))
+                    });
+                    if let Some(spawn_position) = spawn_position {
+                        transform.translation.x = spawn_position.x;
+                        transform.translation.y = spawn_position.y;
+                    } else {
+                        println!("No respawn point or \"Player\" entity in this level, respawning in place");
+                    }
+                    sprite.color = Color::WHITE;
+                    animation.current_state = PlayerAnimationStates::IDLE;
+                    commands.entity(entity)
+                        .remove::<(PlayerDead, HitStun, Knockback)>()
+                        .insert(Invulnerable::new(RESPAWN_INVULNERABILITY_SECONDS));
+                }
+                game_over_state.set(GameOverState::Disabled);
+            },
+            GameOverButtonAction::MainMenu => {
+                println!("Returning to main menu");
+                game_over_state.set(GameOverState::Disabled);
+                app_state.set(AppState::MainMenu);
+            },
+        }
+    }
+}
+
+fn reset_game_over_state(
+    mut game_over_state: ResMut<NextState<GameOverState>>,
+) {
+    game_over_state.set(GameOverState::Disabled);
+}
@@ -1,22 +1,45 @@
 use bevy::prelude::*;
 
-use crate::{AppState, FONT_PATH};
+use crate::{game::OnGameScreen, inventory::Inventory, AppState, FONT_PATH};
 
 pub struct HealthBarPlugin;
 
 const HEART_FULL: &str = "./user_interface/heart_500.png";
 const MANA_FULL: &str = "./user_interface/mana_full_500.png";
+/// Health is counted in half hearts, three hearts on screen.
+pub const PLAYER_MAX_HEALTH: u32 = 6;
+pub const PLAYER_MAX_MANA: f32 = 50.0;
+const MANA_REGEN_PER_SECOND: f32 = 4.0;
 
 impl Plugin for HealthBarPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(AppState::InGame), setup);
-        app.add_systems(Update, (update_health_bar).run_if(in_state(AppState::InGame)));
-        app.insert_resource(PlayerHealth(5));
+        app.add_systems(Update, (update_health_bar, regenerate_mana, update_mana_text, update_coin_text).run_if(in_state(AppState::InGame)));
+        app.insert_resource(PlayerHealth(PLAYER_MAX_HEALTH));
+        app.insert_resource(PlayerMana::default());
     }
 }
 
 #[derive(Resource, Default)]
-struct PlayerHealth(u32);
+pub struct PlayerHealth(pub u32);
+
+#[derive(Resource)]
+pub struct PlayerMana {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Default for PlayerMana {
+    fn default() -> Self {
+        PlayerMana { current: PLAYER_MAX_MANA, max: PLAYER_MAX_MANA }
+    }
+}
+
+#[derive(Component)]
+struct ManaText;
+
+#[derive(Component)]
+struct CoinText;
 
 #[derive(Default)]
 enum HeartHealth {
@@ -41,9 +64,9 @@ fn setup(
     let texture_atlas = TextureAtlasLayout::from_grid(Vec2::new(11.0, 10.0), 3, 1, None, None);
     let texture_atlas_handle = texture_atlases.add(texture_atlas);
 
-    commands.spawn(
+    commands.spawn((
         TextBundle::from_section(
-            "Sample Text",
+            "",
             TextStyle {
                 font: asset_server.load(FONT_PATH),
                 font_size: 10.0,
@@ -56,8 +79,30 @@ fn setup(
                 right: Val::Px(5.0),
                 ..Default::default()
             }
-        )
-    );
+        ),
+        ManaText,
+        OnGameScreen,
+    ));
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load(FONT_PATH),
+                font_size: 10.0,
+                ..Default::default()
+            },
+        ).with_style(
+            Style {
+                position_type: PositionType::Absolute,
+                bottom: Val::Px(20.0),
+                right: Val::Px(5.0),
+                ..Default::default()
+            }
+        ),
+        CoinText,
+        OnGameScreen,
+    ));
 
     commands.spawn((
         NodeBundle {
@@ -73,6 +118,7 @@ fn setup(
             },
             ..Default::default()
         },
+        OnGameScreen,
     ))
     .with_children(|parent| {
         parent.spawn((
@@ -143,13 +189,40 @@ fn update_health_bar(
     player_health: Res<PlayerHealth>,
 ) {
     for (mut atlas_image, heart) in &mut atlas_image {
-        let fill_level = player_health.0 as f32 / heart.id as f32;
-        if fill_level >= 2.0 {
+        let fill_level = player_health.0 as i32 - (heart.id as i32 - 1) * 2;
+        if fill_level >= 2 {
             atlas_image.index = 0;
-        } else if fill_level < 1.5 {
+        } else if fill_level <= 0 {
             atlas_image.index = 2;
         } else {
             atlas_image.index = 1;
         }
-    }   
-}
\ No newline at end of file
+    }
+}
+
+fn regenerate_mana(
+    time: Res<Time>,
+    mut player_mana: ResMut<PlayerMana>,
+) {
+    if player_mana.current < player_mana.max {
+        player_mana.current = (player_mana.current + MANA_REGEN_PER_SECOND * time.delta_seconds()).min(player_mana.max);
+    }
+}
+
+fn update_mana_text(
+    player_mana: Res<PlayerMana>,
+    mut text_query: Query<&mut Text, With<ManaText>>,
+) {
+    for mut text in &mut text_query {
+        text.sections[0].value = format!("Mana: {}/{}", player_mana.current as u32, player_mana.max as u32);
+    }
+}
+
+fn update_coin_text(
+    inventory: Res<Inventory>,
+    mut text_query: Query<&mut Text, With<CoinText>>,
+) {
+    for mut text in &mut text_query {
+        text.sections[0].value = format!("Coins: {}", inventory.coins);
+    }
+}
@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+
+use crate::{game::OnGameScreen, magic::{FireBallSpriteAtlas, IceSpikeSpriteAtlas, SelectedSpell, SpellCoolDowns, Spells}, AppState, FONT_PATH};
+
+pub struct HotbarPlugin;
+
+const SLOT_SIZE: f32 = 64.0;
+const SELECTED_BORDER_COLOR: Color = Color::rgb(0.95, 0.75, 0.2);
+const UNSELECTED_BORDER_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const COOLDOWN_OVERLAY_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.65);
+
+impl Plugin for HotbarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(AppState::InGame), setup);
+        app.add_systems(Update, (update_selected_slot, update_cooldown_overlay).run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Component)]
+struct HotbarSlot {
+    spell: Spells,
+}
+
+#[derive(Component)]
+struct HotbarCooldownOverlay {
+    spell: Spells,
+}
+
+fn setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    fireball_sprite: Res<FireBallSpriteAtlas>,
+    icespike_sprite: Res<IceSpikeSpriteAtlas>,
+    selected_spell: Res<SelectedSpell>,
+) {
+    let font = asset_server.load(FONT_PATH);
+
+    commands.spawn(
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                bottom: Val::Px(10.0),
+                flex_direction: FlexDirection::Row,
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    ).insert(OnGameScreen).with_children(|parent| {
+        for (slot_number, spell) in Spells::ALL.iter().enumerate() {
+            let (image, layout) = match spell {
+                Spells::FireBall => (fireball_sprite.image.clone(), fireball_sprite.layout.clone()),
+                Spells::IceSpike => (icespike_sprite.image.clone(), icespike_sprite.layout.clone()),
+            };
+            let border_color = if *spell == selected_spell.spell {
+                SELECTED_BORDER_COLOR
+            } else {
+                UNSELECTED_BORDER_COLOR
+            };
+
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        width: Val::Px(SLOT_SIZE),
+                        height: Val::Px(SLOT_SIZE),
+                        margin: UiRect::horizontal(Val::Px(4.0)),
+                        border: UiRect::all(Val::Px(3.0)),
+                        ..Default::default()
+                    },
+                    border_color: border_color.into(),
+                    background_color: Color::rgba(0.1, 0.1, 0.1, 0.8).into(),
+                    ..Default::default()
+                },
+                HotbarSlot { spell: *spell },
+            )).with_children(|slot| {
+                slot.spawn(AtlasImageBundle {
+                    style: Style {
+                        width: Val::Percent(100.0),
+                        height: Val::Percent(100.0),
+                        ..Default::default()
+                    },
+                    texture_atlas: TextureAtlas { layout, index: 0 },
+                    image: UiImage::new(image),
+                    ..Default::default()
+                });
+
+                slot.spawn((
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            bottom: Val::Px(0.0),
+                            width: Val::Percent(100.0),
+                            height: Val::Percent(0.0),
+                            ..Default::default()
+                        },
+                        background_color: COOLDOWN_OVERLAY_COLOR.into(),
+                        ..Default::default()
+                    },
+                    HotbarCooldownOverlay { spell: *spell },
+                ));
+
+                slot.spawn(
+                    TextBundle::from_section(
+                        (slot_number + 1).to_string(),
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 14.0,
+                            color: Color::WHITE,
+                        },
+                    ).with_style(Style {
+                        position_type: PositionType::Absolute,
+                        top: Val::Px(2.0),
+                        left: Val::Px(4.0),
+                        ..Default::default()
+                    })
+                );
+            });
+        }
+    });
+}
+
+fn update_selected_slot(
+    selected_spell: Res<SelectedSpell>,
+    mut slot_query: Query<(&HotbarSlot, &mut BorderColor)>,
+) {
+    if !selected_spell.is_changed() {
+        return;
+    }
+
+    for (slot, mut border_color) in &mut slot_query {
+        *border_color = if slot.spell == selected_spell.spell {
+            SELECTED_BORDER_COLOR.into()
+        } else {
+            UNSELECTED_BORDER_COLOR.into()
+        };
+    }
+}
+
+fn update_cooldown_overlay(
+    spell_cooldowns: Res<SpellCoolDowns>,
+    mut overlay_query: Query<(&HotbarCooldownOverlay, &mut Style)>,
+) {
+    for (overlay, mut style) in &mut overlay_query {
+        style.height = Val::Percent(spell_cooldowns.remaining_fraction(overlay.spell) * 100.0);
+    }
+}
@@ -2,10 +2,11 @@ use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_rapier2d::control::KinematicCharacterController;
 
-use crate::player::{ControllablePlayer, PlayerAnimationStates, PlayerSpriteAnimationStates};
+use crate::combat::{HitStun, Knockback};
+use crate::player::{ControllablePlayer, PlayerAnimationStates, PlayerDead, PlayerSpriteAnimationStates};
 use crate::{AppState, CursorWorldCoordinates, PlayerCamera, PlayerInput};
 
-use crate::magic::{spawn_icespike_attack, FireBallSpriteAtlas, IceSpikeSpriteAtlas, SelectedSpell, Spells};
+use crate::magic::{spawn_icespike_attack, FireBallSpriteAtlas, IceSpikeSpriteAtlas, SelectedSpell, SpellCoolDowns, Spells};
 use crate::magic::spawn_fireball_attack;
 
 pub const SPEED: f32 = 200.0;
@@ -23,13 +24,26 @@ impl Plugin for InputPlugin {
 fn keyboard_input_system(
     time: Res<Time>,
     keyboard_input: Res<ButtonInput<KeyCode>>, 
-    mut animation_state_query: Query<&mut PlayerSpriteAnimationStates, With<ControllablePlayer>>,
+    mut animation_state_query: Query<(&mut PlayerSpriteAnimationStates, Option<&HitStun>, Option<&Knockback>, Has<PlayerDead>), With<ControllablePlayer>>,
     mut kinematiccontroller_query: Query<&mut KinematicCharacterController>,
 ) {
-    let mut player = kinematiccontroller_query.single_mut();
+    let Ok(mut player) = kinematiccontroller_query.get_single_mut() else {
+        return;
+    };
     let mut new_velocity = Vec2::ZERO;
 
-    for mut state in &mut animation_state_query {
+    for (mut state, hit_stun, knockback, is_dead) in &mut animation_state_query {
+        if is_dead {
+            player.translation = Some(Vec2::ZERO);
+            return;
+        }
+        let knockback_velocity = knockback.map_or(Vec2::ZERO, |knockback| knockback.velocity * time.delta_seconds());
+        if hit_stun.is_some() {
+            player.translation = Some(knockback_velocity);
+            return;
+        }
+        new_velocity += knockback_velocity;
+
         if keyboard_input.pressed(KeyCode::KeyA) 
                 || keyboard_input.pressed(KeyCode::KeyD) 
                 || keyboard_input.pressed(KeyCode::KeyW)
@@ -42,19 +56,19 @@ fn keyboard_input_system(
     }
 
     if keyboard_input.pressed(KeyCode::KeyA) {
-        new_velocity.x = -SPEED * time.delta_seconds();
+        new_velocity.x += -SPEED * time.delta_seconds();
     }
 
     if keyboard_input.pressed(KeyCode::KeyD) {
-        new_velocity.x = SPEED * time.delta_seconds();
+        new_velocity.x += SPEED * time.delta_seconds();
     }
 
     if keyboard_input.pressed(KeyCode::KeyW) {
-        new_velocity.y = SPEED * time.delta_seconds();
+        new_velocity.y += SPEED * time.delta_seconds();
     }
 
     if keyboard_input.pressed(KeyCode::KeyS) {
-        new_velocity.y = -SPEED * time.delta_seconds();
+        new_velocity.y += -SPEED * time.delta_seconds();
     }
     player.translation = Some(new_velocity);
     
@@ -67,9 +81,14 @@ fn mouse_button_input_system(
     mut commands: Commands,
     cursor_coord: Res<CursorWorldCoordinates>,
     selected_spell: Res<SelectedSpell>,
+    mut spell_cooldowns: ResMut<SpellCoolDowns>,
     fireball_sprite: Res<FireBallSpriteAtlas>,
     icespike_sprite: Res<IceSpikeSpriteAtlas>,
+    dead_player_query: Query<(), (With<ControllablePlayer>, With<PlayerDead>)>,
 ) {
+    if !dead_player_query.is_empty() {
+        return;
+    }
     let (camera, camera_transform) = camera_query.single();
     let window = window_query.single();
 
@@ -79,6 +98,10 @@ fn mouse_button_input_system(
             .map(|ray| ray.origin.truncate()) {
                 println!("Pressed left mouse button");
                 println!("Cursor position is: {},{}", world_position.x, world_position.y);
+                if !spell_cooldowns.is_ready(selected_spell.spell) {
+                    return;
+                }
+                spell_cooldowns.trigger(selected_spell.spell);
                 if selected_spell.spell == Spells::FireBall {
                     spawn_fireball_attack(&mut commands, &fireball_sprite, &cursor_coord, &camera_transform.translation());
                 }
@@ -0,0 +1,205 @@
+use bevy::prelude::*;
+
+use crate::{
+    game::OnGameScreen,
+    player::{ControllablePlayer, PlayerDead},
+    AppState, FONT_PATH, SCALE, TEXT_COLOR,
+};
+
+const KEYBOARD_SPRITES: &str = "./sprites/keyboard/Keyboard.png";
+/// Atlas index of the "A" key, the rest of the alphabet follows in order.
+const KEYBOARD_LETTER_INDEX: usize = 16;
+const LETTER_KEYS: [KeyCode; 26] = [
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF, KeyCode::KeyG,
+    KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL, KeyCode::KeyM, KeyCode::KeyN,
+    KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR, KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU,
+    KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX, KeyCode::KeyY, KeyCode::KeyZ,
+];
+pub const DEFAULT_INTERACT_RADIUS: f32 = 100.0;
+/// Where the prompt sits relative to its target.
+const PROMPT_OFFSET: Vec2 = Vec2::new(40.0, 40.0);
+/// Above the player and everything on the floor.
+const PROMPT_Z: f32 = 10.0;
+
+pub struct InteractionPlugin;
+
+impl Plugin for InteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(KeyboardSpriteAtlas::default());
+        app.add_event::<InteractEvent>();
+        app.add_systems(OnEnter(AppState::LoadingScreen), load_keyboard_sprites);
+        app.add_systems(Update, (
+            add_interactors,
+            select_interaction_targets,
+            send_interact_events,
+        ).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Resource, Default, Clone)]
+pub struct KeyboardSpriteAtlas {
+    layout: Handle<TextureAtlasLayout>,
+    image: Handle<Image>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InteractAction {
+    OpenChest,
+    OpenDoor,
+    PullLever,
+}
+
+/// Something a player can interact with by standing within `radius` and pressing `key`.
+#[derive(Component, Clone, Debug)]
+pub struct Interactable {
+    pub radius: f32,
+    /// Shown next to the key, e.g. "Open".
+    pub prompt: String,
+    pub key: KeyCode,
+    pub action: InteractAction,
+    /// Disabled interactables are skipped, e.g. a chest that has already been opened.
+    pub enabled: bool,
+}
+
+impl Interactable {
+    pub fn new(action: InteractAction, prompt: &str) -> Self {
+        Interactable {
+            radius: DEFAULT_INTERACT_RADIUS,
+            prompt: prompt.to_string(),
+            key: KeyCode::KeyE,
+            action,
+            enabled: true,
+        }
+    }
+}
+
+/// Sent when `actor` presses the interact key while `target` is the nearest interactable in reach.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct InteractEvent {
+    pub actor: Entity,
+    pub target: Entity,
+}
+
+/// Added to every player, tracks the interactable they would use and the prompt shown for it.
+#[derive(Component, Debug, Default)]
+pub struct Interactor {
+    pub target: Option<Entity>,
+    prompt: Option<Entity>,
+    prompt_text: String,
+}
+
+fn load_keyboard_sprites(
+    mut keyboard_sprite_atlas: ResMut<KeyboardSpriteAtlas>,
+    asset_server: Res<AssetServer>,
+    mut texture_atlases: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let texture_atlas = TextureAtlasLayout::from_grid(Vec2::new(16.0, 16.0), 8, 14, None, None);
+    keyboard_sprite_atlas.image = asset_server.load(KEYBOARD_SPRITES);
+    keyboard_sprite_atlas.layout = texture_atlases.add(texture_atlas);
+}
+
+/// Only letters are in the sheet, anything else is shown as "E".
+fn key_sprite_index(key: KeyCode) -> usize {
+    let letter = LETTER_KEYS.iter().position(|letter| *letter == key).unwrap_or(4);
+    KEYBOARD_LETTER_INDEX + letter
+}
+
+/// Shows `key` and the prompt text next to an interactable, returning the prompt entity.
+fn spawn_interaction_prompt(
+    commands: &mut Commands,
+    keyboard_sprites: &KeyboardSpriteAtlas,
+    asset_server: &AssetServer,
+    position: Vec2,
+    interactable: &Interactable,
+) -> Entity {
+    commands.spawn((
+        SpatialBundle::from_transform(Transform::from_translation((position + PROMPT_OFFSET).extend(PROMPT_Z))),
+        OnGameScreen,
+    )).with_children(|prompt| {
+        prompt.spawn(SpriteSheetBundle {
+            texture: keyboard_sprites.image.clone(),
+            atlas: TextureAtlas {
+                layout: keyboard_sprites.layout.clone(),
+                index: key_sprite_index(interactable.key),
+            },
+            transform: Transform::from_scale(Vec3::new(SCALE / 2.0, SCALE / 2.0, 1.0)),
+            ..Default::default()
+        });
+        prompt.spawn(Text2dBundle {
+            text: Text::from_section(
+                interactable.prompt.clone(),
+                TextStyle {
+                    font: asset_server.load(FONT_PATH),
+                    font_size: 24.0,
+                    color: TEXT_COLOR,
+                },
+            ),
+            text_anchor: bevy::sprite::Anchor::CenterLeft,
+            transform: Transform::from_translation(Vec3::new(30.0, 0.0, 0.0)),
+            ..Default::default()
+        });
+    }).id()
+}
+
+fn add_interactors(
+    mut commands: Commands,
+    player_query: Query<Entity, (With<ControllablePlayer>, Without<Interactor>)>,
+) {
+    for player in &player_query {
+        commands.entity(player).insert(Interactor::default());
+    }
+}
+
+/// Picks the nearest enabled interactable in reach of each player and keeps one prompt per player on it.
+fn select_interaction_targets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    keyboard_sprites: Res<KeyboardSpriteAtlas>,
+    mut player_query: Query<(&GlobalTransform, &mut Interactor, Has<PlayerDead>)>,
+    interactable_query: Query<(Entity, &GlobalTransform, &Interactable)>,
+) {
+    for (player_transform, mut interactor, dead) in &mut player_query {
+        let player_position = player_transform.translation().truncate();
+        let nearest = interactable_query.iter()
+            .filter(|(_, _, interactable)| interactable.enabled && !dead)
+            .map(|(entity, global_transform, interactable)| (entity, global_transform.translation().truncate(), interactable))
+            .filter(|(_, position, interactable)| position.distance(player_position) <= interactable.radius)
+            .min_by(|(_, a, _), (_, b, _)| a.distance_squared(player_position).total_cmp(&b.distance_squared(player_position)));
+
+        let target = nearest.map(|(entity, _, _)| entity);
+        let prompt_text = nearest.map(|(_, _, interactable)| interactable.prompt.clone()).unwrap_or_default();
+        // Only rebuild the prompt when it would show something different
+        if target == interactor.target && prompt_text == interactor.prompt_text {
+            continue;
+        }
+
+        if let Some(prompt) = interactor.prompt.take() {
+            commands.entity(prompt).despawn_recursive();
+        }
+        if let Some((_, position, interactable)) = nearest {
+            interactor.prompt = Some(spawn_interaction_prompt(&mut commands, &keyboard_sprites, &asset_server, position, interactable));
+        }
+        interactor.target = target;
+        interactor.prompt_text = prompt_text;
+    }
+}
+
+fn send_interact_events(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    player_query: Query<(Entity, &Interactor)>,
+    interactable_query: Query<&Interactable>,
+    mut interact_events: EventWriter<InteractEvent>,
+) {
+    for (player, interactor) in &player_query {
+        let Some(target) = interactor.target else {
+            continue;
+        };
+        let Ok(interactable) = interactable_query.get(target) else {
+            continue;
+        };
+        if keyboard_input.just_pressed(interactable.key) {
+            println!("Interacting with {:?} ({:?})", target, interactable.action);
+            interact_events.send(InteractEvent { actor: player, target });
+        }
+    }
+}
@@ -1,12 +1,15 @@
+use std::collections::HashMap;
+
 use bevy::{prelude::*, render::view::visibility};
 use bevy_inspector_egui::egui::style;
 
-use crate::{AppState, CursorWorldCoordinates};
+use crate::{game::OnGameScreen, AppState, CursorWorldCoordinates};
 
 pub struct InventoryPlugin;
 
 impl Plugin for InventoryPlugin {
     fn build(&self, app: &mut App) {
+        app.insert_resource(Inventory::default());
         app.add_systems(OnEnter(AppState::InGame), setup);
         app.add_systems(Update, (drag_tile).run_if(in_state(AppState::InGame)));
     }
@@ -15,6 +18,44 @@ impl Plugin for InventoryPlugin {
 #[derive(Component)]
 struct InventoryTile;
 
+/// What the player is carrying, items are counted by id.
+#[derive(Resource, Default, Debug)]
+pub struct Inventory {
+    pub coins: u32,
+    items: HashMap<String, u32>,
+}
+
+impl Inventory {
+    pub fn add(&mut self, item: &str, count: u32) {
+        *self.items.entry(item.to_string()).or_default() += count;
+    }
+
+    pub fn count(&self, item: &str) -> u32 {
+        self.items.get(item).copied().unwrap_or(0)
+    }
+
+    /// Takes `count` of an item, returning false and leaving the inventory untouched if there are not enough.
+    pub fn remove(&mut self, item: &str, count: u32) -> bool {
+        let Some(held) = self.items.get_mut(item) else {
+            return false;
+        };
+        if *held < count {
+            return false;
+        }
+        *held -= count;
+        if *held == 0 {
+            self.items.remove(item);
+        }
+        true
+    }
+
+    /// Uses up the key item with the given id, returning false if it is missing.
+    /// Anything that needs no key is always unlocked.
+    pub fn use_key(&mut self, key: Option<&str>) -> bool {
+        key.map_or(true, |key| self.remove(key, 1))
+    }
+}
+
 fn setup(
     mut commands: Commands,
     asset_Server: Res<AssetServer>,
@@ -38,7 +79,7 @@ fn setup(
             // transform: Transform::from_xyz(100.0, 100.0, 1.0),
             ..Default::default()
         }
-    ).with_children(
+    ).insert(OnGameScreen).with_children(
         |builder| {
             item_rect(builder, Color::ORANGE);
             item_rect(builder, Color::GREEN);
@@ -1,12 +1,17 @@
 use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_ecs_ldtk::ldtk::loaded_level::LoadedLevel;
 use bevy_ecs_ldtk::prelude::*;
 use bevy_rapier2d::prelude::*;
 
-use crate::SCALE;
+use crate::{archetype::{spawn_enemy, EnemyArchetypes}, chest::{spawn_chest, Chest, ChestSpriteAtlas}, collision::Faction, enemy::Enemy, loot::{spawn_pickup, LootDrop, Pickup}, navigation::NavigationGrids, player::ControllablePlayer, spritesheet::{SpriteCollection, TextureAtlases}, AppState, SCALE};
 
 const LEVEL_0_PATH: &str = ".\\level\\level_0.ldtk";
+const KEY_SPRITE_PATH: &str = "./sprites/items and trap_animation/keys/keys_1_1.png";
+/// Level the game starts in, and goes back to when leaving the game.
+pub const START_LEVEL: &str = "Level_0";
 
 pub struct LevelPlugin;
 
@@ -14,8 +19,24 @@ impl Plugin for LevelPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup);
         app.add_systems(Update, spawn_wall_collision);
-        app.insert_resource(LevelSelection::Uid(0));
+        app.insert_resource(LevelSelection::Identifier(START_LEVEL.to_string()));
+        // Levels are laid out in one LDtk world, neighbours of the selected level are streamed in with it
+        app.insert_resource(LdtkSettings {
+            level_spawn_behavior: LevelSpawnBehavior::UseWorldTranslation {
+                load_level_neighbors: true,
+            },
+            ..Default::default()
+        });
+        app.insert_resource(RespawnPoint::default());
         app.register_ldtk_int_cell::<WallBundle>(1);
+        app.register_ldtk_entity::<PlayerStartBundle>("Player");
+        app.register_ldtk_entity::<EnemySpawnPointBundle>("Enemy");
+        app.register_ldtk_entity::<ChestSpawnPointBundle>("Chest");
+        app.register_ldtk_entity::<KeySpawnPointBundle>("Key");
+        // Runs after transform propagation so markers spawned this frame already have their world position
+        app.add_systems(PostUpdate, spawn_level_entities.after(TransformSystem::TransformPropagate).run_if(in_state(AppState::InGame)));
+        app.add_systems(Update, (scope_level_entities, despawn_unloaded_level_entities).chain().run_if(in_state(AppState::InGame)));
+        app.add_systems(OnExit(AppState::InGame), (reset_level_entity_markers, reset_level_selection));
     }
 }
 
@@ -48,6 +69,191 @@ pub struct WallBundle {
     wall: Wall,
 }
 
+/// Where the player spawns and respawns, placed as the "Player" entity in the LDtk level.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct PlayerStart;
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct PlayerStartBundle {
+    player_start: PlayerStart,
+}
+
+/// Spawns an enemy of the archetype named in its "archetype" field.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct EnemySpawnPoint {
+    pub archetype: String,
+}
+
+impl From<&EntityInstance> for EnemySpawnPoint {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        EnemySpawnPoint {
+            archetype: entity_instance.get_string_field("archetype").cloned().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct EnemySpawnPointBundle {
+    #[from_entity_instance]
+    enemy_spawn_point: EnemySpawnPoint,
+}
+
+/// Spawns a chest that drops from the loot table named in its "loot_table" field.
+/// A chest with a "key" field stays locked until opened with that key.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct ChestSpawnPoint {
+    pub loot_table: String,
+    pub key: Option<String>,
+}
+
+impl From<&EntityInstance> for ChestSpawnPoint {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        ChestSpawnPoint {
+            loot_table: entity_instance.get_string_field("loot_table").cloned().unwrap_or_default(),
+            key: key_field(entity_instance),
+        }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct ChestSpawnPointBundle {
+    #[from_entity_instance]
+    chest_spawn_point: ChestSpawnPoint,
+}
+
+/// Spawns a key pickup with the id in its "key" field, for locked doors and chests to ask for.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct KeySpawnPoint {
+    pub key: String,
+}
+
+impl From<&EntityInstance> for KeySpawnPoint {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        KeySpawnPoint {
+            key: key_field(entity_instance).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct KeySpawnPointBundle {
+    #[from_entity_instance]
+    key_spawn_point: KeySpawnPoint,
+}
+
+/// The "key" field of a locked entity, None when it is unset or empty.
+pub fn key_field(entity_instance: &EntityInstance) -> Option<String> {
+    entity_instance.get_string_field("key").ok()
+        .filter(|key| !key.is_empty())
+        .cloned()
+}
+
+/// Where the player respawns after dying: the "Player" entity at the start of the game,
+/// then the last entrance they came through.
+#[derive(Resource, Default, Debug)]
+pub struct RespawnPoint(pub Option<Vec2>);
+
+/// The level entity a spawned enemy, chest or pickup belongs to.
+/// They are not children of the level, so they are despawned here when the level unloads.
+#[derive(Component, Debug)]
+pub struct LevelScoped(pub Entity);
+
+/// Added to level markers once the game entity they describe has been spawned,
+/// and cleared when leaving the game so they spawn again next time.
+#[derive(Component)]
+pub struct LevelEntitySpawned;
+
+fn spawn_level_entities(
+    mut commands: Commands,
+    enemy_archetypes: Res<EnemyArchetypes>,
+    texture_atlas: Res<TextureAtlases>,
+    sprite_collection: Res<SpriteCollection>,
+    chest_sprite_atlas: Res<ChestSpriteAtlas>,
+    player_start_query: Query<(Entity, &GlobalTransform), (With<PlayerStart>, Without<LevelEntitySpawned>)>,
+    enemy_spawn_query: Query<(Entity, &GlobalTransform, &EnemySpawnPoint), Without<LevelEntitySpawned>>,
+    chest_spawn_query: Query<(Entity, &GlobalTransform, &ChestSpawnPoint), Without<LevelEntitySpawned>>,
+    key_spawn_query: Query<(Entity, &GlobalTransform, &KeySpawnPoint), Without<LevelEntitySpawned>>,
+    asset_server: Res<AssetServer>,
+    mut player_query: Query<&mut Transform, With<ControllablePlayer>>,
+    mut respawn_point: ResMut<RespawnPoint>,
+) {
+    for (entity, global_transform) in &player_start_query {
+        let Ok(mut player_transform) = player_query.get_single_mut() else {
+            continue;
+        };
+        // Only place the player once per game, a start level streamed back in later must not teleport them
+        if respawn_point.0.is_none() {
+            player_transform.translation.x = global_transform.translation().x;
+            player_transform.translation.y = global_transform.translation().y;
+            respawn_point.0 = Some(global_transform.translation().truncate());
+        }
+        commands.entity(entity).insert(LevelEntitySpawned);
+    }
+
+    for (entity, global_transform, spawn_point) in &enemy_spawn_query {
+        spawn_enemy(&mut commands, &enemy_archetypes, &texture_atlas, &sprite_collection, &spawn_point.archetype, global_transform.translation().truncate());
+        commands.entity(entity).insert(LevelEntitySpawned);
+    }
+
+    for (entity, global_transform, spawn_point) in &chest_spawn_query {
+        spawn_chest(&mut commands, &chest_sprite_atlas, global_transform.translation().truncate(), spawn_point.loot_table.clone(), spawn_point.key.clone());
+        commands.entity(entity).insert(LevelEntitySpawned);
+    }
+
+    for (entity, global_transform, spawn_point) in &key_spawn_query {
+        let drop = LootDrop::Item { id: spawn_point.key.clone(), sprite: KEY_SPRITE_PATH.to_string() };
+        spawn_pickup(&mut commands, &asset_server, drop, 1, global_transform.translation().truncate());
+        commands.entity(entity).insert(LevelEntitySpawned);
+    }
+}
+
+fn reset_level_entity_markers(
+    mut commands: Commands,
+    marker_query: Query<Entity, With<LevelEntitySpawned>>,
+    mut respawn_point: ResMut<RespawnPoint>,
+) {
+    for entity in &marker_query {
+        commands.entity(entity).remove::<LevelEntitySpawned>();
+    }
+    respawn_point.0 = None;
+}
+
+fn reset_level_selection(
+    mut level_selection: ResMut<LevelSelection>,
+) {
+    *level_selection = LevelSelection::Identifier(START_LEVEL.to_string());
+}
+
+/// Ties enemies, chests and pickups to the level they are standing in.
+/// Anything spawned before its level's navigation grid exists is picked up on a later frame.
+fn scope_level_entities(
+    mut commands: Commands,
+    navigation_grids: Res<NavigationGrids>,
+    unscoped_query: Query<(Entity, &Transform), (Without<LevelScoped>, Or<(With<Enemy>, With<Chest>, With<Pickup>)>)>,
+) {
+    for (entity, transform) in &unscoped_query {
+        let position = transform.translation.truncate();
+        let Some(level_entity) = navigation_grids.grids.iter()
+            .find(|(_, grid)| grid.contains(grid.world_to_cell(position)))
+            .map(|(level_entity, _)| *level_entity) else {
+            continue;
+        };
+        commands.entity(entity).insert(LevelScoped(level_entity));
+    }
+}
+
+fn despawn_unloaded_level_entities(
+    mut commands: Commands,
+    scoped_query: Query<(Entity, &LevelScoped)>,
+    entities: &Entities,
+) {
+    for (entity, scope) in &scoped_query {
+        if !entities.contains(scope.0) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
 /// Spawns heron collisions for the walls of a level
 ///
 /// You could just insert a ColliderBundle in to the WallBundle,
@@ -57,37 +263,15 @@ pub struct WallBundle {
 /// Instead, by flagging the wall tiles and spawning the collisions later,
 /// we can minimize the amount of colliding entities.
 ///
-/// The algorithm used here is a nice compromise between simplicity, speed,
-/// and a small number of rectangle colliders.
-/// In basic terms, it will:
-/// 1. consider where the walls are
-/// 2. combine wall tiles into flat "plates" in each individual row
-/// 3. combine the plates into rectangles across multiple rows wherever possible
-/// 4. spawn colliders for each rectangle
+/// The merging itself lives in `merge_wall_rects` so generated levels can share it.
 pub fn spawn_wall_collision(
     mut commands: Commands,
     wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
     parent_query: Query<&Parent, Without<Wall>>,
-    level_query: Query<(Entity, &LevelIid)>,
+    level_query: Query<(Entity, &LevelIid, &Parent)>,
     ldtk_projects: Query<&Handle<LdtkProject>>,
     ldtk_project_assets: Res<Assets<LdtkProject>>,
 ) {
-    /// Represents a wide wall that is 1 tile tall
-    /// Used to spawn wall collisions
-    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
-    struct Plate {
-        left: i32,
-        right: i32,
-    }
-
-    /// A simple rectangle type representing a wall of any size
-    struct Rect {
-        left: i32,
-        right: i32,
-        top: i32,
-        bottom: i32,
-    }
-
     // Consider where the walls are
     // storing them as GridCoords in a HashSet for quick, easy lookup
     //
@@ -110,10 +294,11 @@ pub fn spawn_wall_collision(
     });
 
     if !wall_query.is_empty() {
-        level_query.iter().for_each(|(level_entity, level_iid)| {
+        level_query.iter().for_each(|(level_entity, level_iid, world)| {
             if let Some(level_walls) = level_to_wall_locations.get(&level_entity) {
-                let ldtk_project = ldtk_project_assets
-                    .get(ldtk_projects.single())
+                // Levels are children of the world that loaded them
+                let ldtk_project = ldtk_projects.get(world.get()).ok()
+                    .and_then(|ldtk_handle| ldtk_project_assets.get(ldtk_handle))
                     .expect("Project should be loaded if level has spawned");
 
                 let level = ldtk_project
@@ -121,98 +306,145 @@ pub fn spawn_wall_collision(
                     .get_loaded_level_by_iid(&level_iid.to_string())
                     .expect("Spawned level should exist in LDtk project");
 
-                let LayerInstance {
+                let Some(&LayerInstance {
                     c_wid: width,
                     c_hei: height,
                     grid_size,
                     ..
-                } = level.layer_instances()[0];
-
-                // combine wall tiles into flat "plates" in each individual row
-                let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
-
-                for y in 0..height {
-                    let mut row_plates: Vec<Plate> = Vec::new();
-                    let mut plate_start = None;
-
-                    // + 1 to the width so the algorithm "terminates" plates that touch the right edge
-                    for x in 0..width + 1 {
-                        match (plate_start, level_walls.contains(&GridCoords { x, y })) {
-                            (Some(s), false) => {
-                                row_plates.push(Plate {
-                                    left: s,
-                                    right: x - 1,
-                                });
-                                plate_start = None;
-                            }
-                            (None, true) => plate_start = Some(x),
-                            _ => (),
-                        }
-                    }
-
-                    plate_stack.push(row_plates);
-                }
+                }) = collision_layer(&level) else {
+                    println!("Level {} has no {} layer", level_iid, COLLISION_LAYER);
+                    return;
+                };
 
-                // combine "plates" into rectangles across multiple rows
-                let mut rect_builder: HashMap<Plate, Rect> = HashMap::new();
-                let mut prev_row: Vec<Plate> = Vec::new();
-                let mut wall_rects: Vec<Rect> = Vec::new();
-
-                // an extra empty row so the algorithm "finishes" the rects that touch the top edge
-                plate_stack.push(Vec::new());
-
-                for (y, current_row) in plate_stack.into_iter().enumerate() {
-                    for prev_plate in &prev_row {
-                        if !current_row.contains(prev_plate) {
-                            // remove the finished rect so that the same plate in the future starts a new rect
-                            if let Some(rect) = rect_builder.remove(prev_plate) {
-                                wall_rects.push(rect);
-                            }
-                        }
-                    }
-                    for plate in &current_row {
-                        rect_builder
-                            .entry(plate.clone())
-                            .and_modify(|e| e.top += 1)
-                            .or_insert(Rect {
-                                bottom: y as i32,
-                                top: y as i32,
-                                left: plate.left,
-                                right: plate.right,
-                            });
-                    }
-                    prev_row = current_row;
-                }
+                let wall_rects = merge_wall_rects(level_walls, width, height);
 
+                // Making the colliders children of the level serves two purposes:
+                // 1. Adjusts the transforms to be relative to the level for free
+                // 2. the colliders will be despawned automatically when levels unload
                 commands.entity(level_entity).with_children(|level| {
-                    // Spawn colliders for every rectangle..
-                    // Making the collider a child of the level serves two purposes:
-                    // 1. Adjusts the transforms to be relative to the level for free
-                    // 2. the colliders will be despawned automatically when levels unload
-                    for wall_rect in wall_rects {
-                        level
-                            .spawn_empty()
-                            .insert(Collider::cuboid(
-                                (wall_rect.right as f32 - wall_rect.left as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                                (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.)
-                                    * grid_size as f32
-                                    / 2.,
-                            ))
-                            .insert(RigidBody::Fixed)
-                            .insert(Friction::new(1.0))
-                            .insert(Transform::from_xyz(
-                                (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32
-                                    / 2.,
-                                (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32
-                                    / 2.,
-                                0.,
-                            ))
-                            .insert(GlobalTransform::default());
-                    }
+                    spawn_wall_colliders(level, wall_rects, grid_size);
                 });
             }
         });
     }
-}
\ No newline at end of file
+}
+
+/// Identifier of the IntGrid layer holding the walls.
+pub const COLLISION_LAYER: &str = "Collisions";
+
+/// The walls layer of a level, found by name so layers can be reordered in LDtk.
+pub fn collision_layer<'a>(level: &'a LoadedLevel) -> Option<&'a LayerInstance> {
+    level.layer_instances().iter().find(|layer| layer.identifier == COLLISION_LAYER)
+}
+
+/// A simple rectangle type representing a wall of any size, in grid cells
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct WallRect {
+    pub left: i32,
+    pub right: i32,
+    pub top: i32,
+    pub bottom: i32,
+}
+
+/// Combines wall tiles into a small number of rectangles.
+///
+/// The algorithm used here is a nice compromise between simplicity, speed,
+/// and a small number of rectangle colliders.
+/// In basic terms, it will:
+/// 1. combine wall tiles into flat "plates" in each individual row
+/// 2. combine the plates into rectangles across multiple rows wherever possible
+pub fn merge_wall_rects(walls: &HashSet<GridCoords>, width: i32, height: i32) -> Vec<WallRect> {
+    /// Represents a wide wall that is 1 tile tall
+    /// Used to spawn wall collisions
+    #[derive(Clone, Eq, PartialEq, Debug, Default, Hash)]
+    struct Plate {
+        left: i32,
+        right: i32,
+    }
+
+    // combine wall tiles into flat "plates" in each individual row
+    let mut plate_stack: Vec<Vec<Plate>> = Vec::new();
+
+    for y in 0..height {
+        let mut row_plates: Vec<Plate> = Vec::new();
+        let mut plate_start = None;
+
+        // + 1 to the width so the algorithm "terminates" plates that touch the right edge
+        for x in 0..width + 1 {
+            match (plate_start, walls.contains(&GridCoords { x, y })) {
+                (Some(s), false) => {
+                    row_plates.push(Plate {
+                        left: s,
+                        right: x - 1,
+                    });
+                    plate_start = None;
+                }
+                (None, true) => plate_start = Some(x),
+                _ => (),
+            }
+        }
+
+        plate_stack.push(row_plates);
+    }
+
+    // combine "plates" into rectangles across multiple rows
+    let mut rect_builder: HashMap<Plate, WallRect> = HashMap::new();
+    let mut prev_row: Vec<Plate> = Vec::new();
+    let mut wall_rects: Vec<WallRect> = Vec::new();
+
+    // an extra empty row so the algorithm "finishes" the rects that touch the top edge
+    plate_stack.push(Vec::new());
+
+    for (y, current_row) in plate_stack.into_iter().enumerate() {
+        for prev_plate in &prev_row {
+            if !current_row.contains(prev_plate) {
+                // remove the finished rect so that the same plate in the future starts a new rect
+                if let Some(rect) = rect_builder.remove(prev_plate) {
+                    wall_rects.push(rect);
+                }
+            }
+        }
+        for plate in &current_row {
+            rect_builder
+                .entry(plate.clone())
+                .and_modify(|e| e.top += 1)
+                .or_insert(WallRect {
+                    bottom: y as i32,
+                    top: y as i32,
+                    left: plate.left,
+                    right: plate.right,
+                });
+        }
+        prev_row = current_row;
+    }
+
+    wall_rects
+}
+
+/// Spawns a fixed collider for every rectangle, positioned in level pixels relative to `level`.
+pub fn spawn_wall_colliders(level: &mut ChildBuilder, wall_rects: Vec<WallRect>, grid_size: i32) {
+    for wall_rect in wall_rects {
+        level
+            .spawn_empty()
+            .insert(Collider::cuboid(
+                (wall_rect.right as f32 - wall_rect.left as f32 + 1.)
+                    * grid_size as f32
+                    / 2.,
+                (wall_rect.top as f32 - wall_rect.bottom as f32 + 1.)
+                    * grid_size as f32
+                    / 2.,
+            ))
+            .insert(RigidBody::Fixed)
+            .insert(Friction::new(1.0))
+            .insert(Faction::Wall)
+            .insert(Faction::Wall.collision_groups())
+            .insert(Transform::from_xyz(
+                (wall_rect.left + wall_rect.right + 1) as f32 * grid_size as f32
+                    / 2.,
+                (wall_rect.bottom + wall_rect.top + 1) as f32 * grid_size as f32
+                    / 2.,
+                0.,
+            ))
+            .insert(GlobalTransform::default());
+    }
+}
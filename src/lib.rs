@@ -3,6 +3,8 @@ pub mod tilemap;
 pub mod mainmenu;
 pub mod splashscreen;
 pub mod level;
+pub mod transition;
+pub mod dungeon;
 pub mod magic;
 pub mod input;
 pub mod cursor;
@@ -13,8 +15,25 @@ pub mod network;
 pub mod player;
 pub mod healthbar;
 pub mod enemy;
+pub mod archetype;
+pub mod boss;
+pub mod behaviour;
+pub mod navigation;
+pub mod spawner;
+pub mod traps;
+pub mod steering;
 pub mod chest;
+pub mod door;
+pub mod interaction;
 pub mod inventory;
+pub mod hotbar;
+pub mod loot;
+pub mod status_effect;
+pub mod collision;
+pub mod combat;
+pub mod damage;
+pub mod rng;
+pub mod gameover;
 
 use std::time::Duration;
 
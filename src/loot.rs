@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    archetype::EnemyArchetypes,
+    collision::{Faction, FactionCollisionEvent},
+    enemy::EnemyDeathEvent,
+    game::OnGameScreen,
+    healthbar::{PlayerHealth, PlayerMana, PLAYER_MAX_HEALTH},
+    inventory::Inventory,
+    player::{ControllablePlayer, PlayerDead},
+    rng::{GameRng, RngStream},
+    AppState, SCALE,
+};
+
+const LOOT_TABLES_PATH: &str = "./assets/data/loot_tables.json";
+const COIN_SPRITE_PATH: &str = "./sprites/items and trap_animation/coin/coin_1.png";
+const HEALTH_FLASK_SPRITE_PATH: &str = "./sprites/items and trap_animation/flasks/flasks_1_1.png";
+const MANA_FLASK_SPRITE_PATH: &str = "./sprites/items and trap_animation/flasks/flasks_2_1.png";
+/// How far from the source drops are scattered.
+const DROP_SCATTER: f32 = 40.0;
+/// Pickups within this distance slide toward the player.
+const MAGNET_RADIUS: f32 = 180.0;
+const MAGNET_SPEED: f32 = 450.0;
+
+pub struct LootPlugin;
+
+impl Plugin for LootPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LootTables::default());
+        app.add_event::<PickupEvent>();
+        app.add_systems(Startup, load_loot_tables);
+        app.add_systems(Update, (drop_enemy_loot, attract_pickups, collect_pickups, apply_pickups).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+pub enum LootDrop {
+    /// Weighted entry that drops nothing, so tables can make drops rare.
+    Nothing,
+    Coins,
+    /// Restores the given number of half hearts.
+    Health,
+    Mana,
+    Item { id: String, sprite: String },
+}
+
+impl LootDrop {
+    fn sprite_path(&self) -> Option<String> {
+        match self {
+            LootDrop::Nothing => None,
+            LootDrop::Coins => Some(COIN_SPRITE_PATH.to_string()),
+            LootDrop::Health => Some(HEALTH_FLASK_SPRITE_PATH.to_string()),
+            LootDrop::Mana => Some(MANA_FLASK_SPRITE_PATH.to_string()),
+            LootDrop::Item { sprite, .. } => Some(sprite.clone()),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct LootEntry {
+    pub drop: LootDrop,
+    pub weight: u32,
+    #[serde(default = "default_amount")]
+    pub min: u32,
+    #[serde(default = "default_amount")]
+    pub max: u32,
+}
+
+fn default_amount() -> u32 {
+    1
+}
+
+/// Weighted drops rolled `rolls` times, as described in `assets/data/loot_tables.json`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct LootTable {
+    #[serde(default = "default_amount")]
+    pub rolls: u32,
+    pub entries: Vec<LootEntry>,
+}
+
+impl LootTable {
+    pub fn roll(&self, rng: &mut impl Rng) -> Vec<(LootDrop, u32)> {
+        let total_weight: u32 = self.entries.iter().map(|entry| entry.weight).sum();
+        if total_weight == 0 {
+            return Vec::new();
+        }
+
+        let mut drops = Vec::new();
+        for _ in 0..self.rolls {
+            let mut pick = rng.gen_range(0..total_weight);
+            let Some(entry) = self.entries.iter().find(|entry| {
+                if pick < entry.weight {
+                    return true;
+                }
+                pick -= entry.weight;
+                false
+            }) else {
+                continue;
+            };
+            if entry.drop != LootDrop::Nothing {
+                drops.push((entry.drop.clone(), rng.gen_range(entry.min..=entry.max.max(entry.min))));
+            }
+        }
+        drops
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct LootTables {
+    pub tables: HashMap<String, LootTable>,
+}
+
+/// A dropped item lying in the world, collected by walking over it.
+#[derive(Component, Debug)]
+pub struct Pickup {
+    pub drop: LootDrop,
+    pub amount: u32,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct PickupEvent {
+    pub player: Entity,
+    pub drop: LootDrop,
+    pub amount: u32,
+}
+
+fn load_loot_tables(
+    mut loot_tables: ResMut<LootTables>,
+) {
+    let mut file = File::open(LOOT_TABLES_PATH).expect("Failed to open loot tables file");
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).expect("Failed to read loot tables file");
+    let tables: HashMap<String, LootTable> = serde_json::from_str(&contents).expect("Failed to parse loot tables");
+
+    for (id, table) in tables {
+        println!("Loaded loot table {}", id);
+        loot_tables.tables.insert(id, table);
+    }
+}
+
+/// Rolls a loot table and scatters the drops around `position`.
+pub fn spawn_loot(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    loot_tables: &LootTables,
+    table_id: &str,
+    position: Vec2,
+    rng: &mut impl Rng,
+) {
+    let Some(table) = loot_tables.tables.get(table_id) else {
+        println!("Unknown loot table {}", table_id);
+        return;
+    };
+    for (drop, amount) in table.roll(rng) {
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let offset = Vec2::new(angle.cos(), angle.sin()) * rng.gen_range(0.0..DROP_SCATTER);
+        spawn_pickup(commands, asset_server, drop, amount, position + offset);
+    }
+}
+
+pub fn spawn_pickup(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    drop: LootDrop,
+    amount: u32,
+    position: Vec2,
+) -> Option<Entity> {
+    let sprite_path = drop.sprite_path()?;
+    let name = format!("Pickup ({:?} x{})", drop, amount);
+
+    Some(commands.spawn((
+        SpriteBundle {
+            texture: asset_server.load(sprite_path),
+            transform: Transform {
+                translation: position.extend(4.0),
+                scale: Vec3::new(SCALE / 2.0, SCALE / 2.0, 1.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Pickup { drop, amount },
+        Collider::ball(6.0),
+        Sensor,
+        Faction::Pickup,
+        Faction::Pickup.collision_groups(),
+        // The player is a kinematic body and pickups have no rigid body, which rapier skips by default
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+        ActiveEvents::COLLISION_EVENTS,
+        Name::new(name),
+        OnGameScreen,
+    )).id())
+}
+
+fn drop_enemy_loot(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    loot_tables: Res<LootTables>,
+    enemy_archetypes: Res<EnemyArchetypes>,
+    mut death_events: EventReader<EnemyDeathEvent>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let rng = game_rng.stream(RngStream::Loot);
+    for event in death_events.read() {
+        let Some(table_id) = event.archetype.as_deref()
+            .and_then(|archetype| enemy_archetypes.get(archetype))
+            .and_then(|archetype| archetype.loot_table.as_deref()) else {
+            continue;
+        };
+        spawn_loot(&mut commands, &asset_server, &loot_tables, table_id, event.position, rng);
+    }
+}
+
+fn attract_pickups(
+    time: Res<Time>,
+    mut pickup_query: Query<&mut Transform, With<Pickup>>,
+    player_query: Query<&Transform, (With<ControllablePlayer>, Without<PlayerDead>, Without<Pickup>)>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let player_position = player_transform.translation.truncate();
+
+    for mut transform in &mut pickup_query {
+        let offset = player_position - transform.translation.truncate();
+        let distance = offset.length();
+        if distance > MAGNET_RADIUS || distance <= f32::EPSILON {
+            continue;
+        }
+        // Pull harder the closer the pickup gets so it snaps in rather than orbiting
+        let pull = MAGNET_SPEED * (1.0 - distance / MAGNET_RADIUS).max(0.2);
+        let step = (pull * time.delta_seconds()).min(distance);
+        transform.translation += (offset / distance * step).extend(0.0);
+    }
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    mut collision_events: EventReader<FactionCollisionEvent>,
+    pickup_query: Query<&Pickup>,
+    mut pickup_events: EventWriter<PickupEvent>,
+) {
+    for event in collision_events.read() {
+        if !event.started || !event.is(Faction::Pickup, Faction::Player) {
+            continue;
+        }
+        let Ok(pickup) = pickup_query.get(event.entity) else {
+            continue;
+        };
+        pickup_events.send(PickupEvent {
+            player: event.other,
+            drop: pickup.drop.clone(),
+            amount: pickup.amount,
+        });
+        commands.entity(event.entity).despawn_recursive();
+    }
+}
+
+fn apply_pickups(
+    mut pickup_events: EventReader<PickupEvent>,
+    mut inventory: ResMut<Inventory>,
+    mut player_health: ResMut<PlayerHealth>,
+    mut player_mana: ResMut<PlayerMana>,
+) {
+    for event in pickup_events.read() {
+        match &event.drop {
+            LootDrop::Nothing => {},
+            LootDrop::Coins => inventory.coins += event.amount,
+            LootDrop::Health => player_health.0 = (player_health.0 + event.amount).min(PLAYER_MAX_HEALTH),
+            LootDrop::Mana => player_mana.current = (player_mana.current + event.amount as f32).min(player_mana.max),
+            LootDrop::Item { id, .. } => inventory.add(id, event.amount),
+        }
+        println!("Picked up {:?} x{}", event.drop, event.amount);
+    }
+}
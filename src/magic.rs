@@ -1,7 +1,10 @@
-use crate::{CursorWorldCoordinates, PlayerCamera};
+use crate::{collision::{Faction, FactionCollisionEvent}, combat::HitEvent, damage::{Damage, DamageType}, status_effect::{StatusEffect, StatusEffectKind, StatusEffects}, CursorWorldCoordinates, PlayerCamera};
+use bevy::input::mouse::MouseWheel;
 use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
 use bevy::window::PrimaryWindow;
 use bevy_rapier2d::prelude::*;
+use serde::Deserialize;
 use std::f32::consts::PI;
 use std::time::Duration;
 
@@ -37,13 +40,63 @@ pub struct SelectedSpell {
     pub spell: Spells,
 }
 
-#[derive(Default, PartialEq, Debug)]
+#[derive(Default, PartialEq, Eq, Hash, Clone, Copy, Debug, Deserialize)]
 pub enum Spells {
     #[default]
     FireBall,
     IceSpike,
 }
 
+impl Spells {
+    pub const ALL: [Spells; 2] = [Spells::FireBall, Spells::IceSpike];
+
+    pub fn cooldown(&self) -> Duration {
+        match self {
+            Spells::FireBall => Duration::from_millis(600),
+            Spells::IceSpike => Duration::from_millis(1500),
+        }
+    }
+
+    pub fn damage_type(&self) -> DamageType {
+        match self {
+            Spells::FireBall => DamageType::Fire,
+            Spells::IceSpike => DamageType::Ice,
+        }
+    }
+
+    pub fn projectile_speed(&self) -> f32 {
+        match self {
+            Spells::FireBall => 300.0,
+            Spells::IceSpike => 600.0,
+        }
+    }
+
+    /// Distance from the centre of the sprite to the head of the projectile, in sprite pixels.
+    fn collision_offset(&self) -> f32 {
+        match self {
+            Spells::FireBall => 18.0 * 2.0,
+            Spells::IceSpike => 29.0,
+        }
+    }
+
+    fn projectile_behaviour(&self) -> ProjectileBehaviour {
+        match self {
+            Spells::FireBall => ProjectileBehaviour { bounces_remaining: 0, pierces_remaining: 0 },
+            Spells::IceSpike => ProjectileBehaviour { bounces_remaining: 1, pierces_remaining: 1 },
+        }
+    }
+
+    pub fn next(&self) -> Spells {
+        let index = Spells::ALL.iter().position(|spell| spell == self).unwrap_or(0);
+        Spells::ALL[(index + 1) % Spells::ALL.len()]
+    }
+
+    pub fn previous(&self) -> Spells {
+        let index = Spells::ALL.iter().position(|spell| spell == self).unwrap_or(0);
+        Spells::ALL[(index + Spells::ALL.len() - 1) % Spells::ALL.len()]
+    }
+}
+
 #[derive(Component)]
 struct FireBall {
     _last_index: u32,
@@ -54,14 +107,58 @@ struct IceSpike {
     _last_index: u32,
 }
 
+/// Optional projectile properties. Bounces are spent on walls, pierces on enemies;
+/// once both run out the next impact destroys the projectile.
+#[derive(Component, Clone, Copy, Default)]
+struct ProjectileBehaviour {
+    bounces_remaining: u32,
+    pierces_remaining: u32,
+}
+
 #[derive(Component)]
 struct SpellFlightTime {
     timer: Timer,
 }
 
-#[derive(Resource, Default)]
-pub struct SpellCoolDown{
-    timer: Timer,
+#[derive(Resource)]
+pub struct SpellCoolDowns {
+    timers: HashMap<Spells, Timer>,
+}
+
+impl Default for SpellCoolDowns {
+    fn default() -> Self {
+        let mut timers = HashMap::new();
+        for spell in Spells::ALL {
+            let mut timer = Timer::new(spell.cooldown(), TimerMode::Once);
+            timer.tick(spell.cooldown());
+            timers.insert(spell, timer);
+        }
+        SpellCoolDowns { timers }
+    }
+}
+
+impl SpellCoolDowns {
+    pub fn is_ready(&self, spell: Spells) -> bool {
+        self.timers.get(&spell).map_or(true, |timer| timer.finished())
+    }
+
+    pub fn trigger(&mut self, spell: Spells) {
+        if let Some(timer) = self.timers.get_mut(&spell) {
+            timer.reset();
+        }
+    }
+
+    /// Fraction of the cooldown still remaining, 1.0 right after casting and 0.0 once ready.
+    pub fn remaining_fraction(&self, spell: Spells) -> f32 {
+        self.timers.get(&spell).map_or(0.0, |timer| timer.fraction_remaining())
+    }
+}
+
+/// Damage a projectile fired by an enemy deals to the player.
+#[derive(Component, Clone, Copy)]
+pub struct EnemyProjectile {
+    pub damage: Damage,
+    pub knockback: f32,
 }
 
 #[derive(Resource, Default, Clone)]
@@ -78,14 +175,22 @@ struct IceSpikeShatterSpriteAtlas {
 
 #[derive(Resource, Default, Clone)]
 pub struct FireBallSpriteAtlas {
-    image: Handle<Image>,
-    layout: Handle<TextureAtlasLayout>,
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
 }
 
 #[derive(Resource, Default, Clone)]
 pub struct IceSpikeSpriteAtlas {
-    image: Handle<Image>,
-    layout: Handle<TextureAtlasLayout>,
+    pub image: Handle<Image>,
+    pub layout: Handle<TextureAtlasLayout>,
+}
+
+/// Sent by everything that ends a projectile: running out of time, walls and hits.
+/// `despawn_projectiles` handles them together so each projectile is only despawned and shattered once.
+#[derive(Debug, Clone, Event)]
+struct ProjectileDespawnEvent {
+    projectile: Entity,
+    impact_position: Vec3,
 }
 
 #[derive(Debug, Clone, Event)]
@@ -98,9 +203,15 @@ impl Plugin for MagicPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(Startup, setup);
         app.add_systems(Update, (
-            despawn_spells,
+            (
+                despawn_spells,
+                receive_enemy_spell_collision_event,
+                enemy_projectile_player_collision,
+                projectile_wall_collision,
+                despawn_projectiles,
+            ).chain(),
             // spell_flight_system,
-            receive_enemy_spell_collision_event,
+            orient_projectiles,
             select_spell_system,
             enable_spell_cooldown,
             cursor_system,
@@ -111,13 +222,14 @@ impl Plugin for MagicPlugin {
         ));
 
         app.add_event::<EnemySpellCollisionEvent>();
+        app.add_event::<ProjectileDespawnEvent>();
 
         app.insert_resource(FireBallSpriteAtlas::default());
         app.insert_resource(FireBurstSpriteAtlas::default());
         app.insert_resource(IceSpikeSpriteAtlas::default());
         app.insert_resource(IceSpikeShatterSpriteAtlas::default());
         app.insert_resource(SelectedSpell::default());
-        app.insert_resource(SpellCoolDown::default());
+        app.insert_resource(SpellCoolDowns::default());
         app.insert_resource(CursorWorldCoordinates::default());
     }
 }
@@ -198,6 +310,7 @@ fn cursor_system(
 fn select_spell_system(
     mut selected_spell: ResMut<SelectedSpell>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
 ) {
     if keyboard_input.pressed(KeyCode::Digit1) && selected_spell.spell != Spells::FireBall {
         println!("Switched to Fire Ball");
@@ -207,32 +320,100 @@ fn select_spell_system(
         println!("Switched to Ice Spike");
         selected_spell.spell = Spells::IceSpike;
     }
+
+    for event in mouse_wheel_events.read() {
+        if event.y > 0.0 {
+            selected_spell.spell = selected_spell.spell.previous();
+        } else if event.y < 0.0 {
+            selected_spell.spell = selected_spell.spell.next();
+        }
+    }
 }
 
 fn despawn_spells(
-    mut commands: Commands,
     mut timer_query: Query<(Entity, &Transform, &mut SpellFlightTime, &CastSpell)>,
-    fireburst_sprite: Res<FireBurstSpriteAtlas>,
-    icespikeshatter_sprite: Res<IceSpikeShatterSpriteAtlas>,
+    mut despawn_events: EventWriter<ProjectileDespawnEvent>,
     time: Res<Time>,
 ) {
     for (entity, &transform, mut spell_timer, cast_spell) in timer_query.iter_mut() {
         spell_timer.timer.tick(time.delta());
 
-        if spell_timer.timer.finished() {
+        if spell_timer.timer.just_finished() {
             let impact_position = transform.translation + (cast_spell.direction * SCALE/2.0 * cast_spell.collision_offset).extend(1.0);
-            println!("Cast spell type attribute: {:?}", cast_spell.spell_type);
-            if cast_spell.spell_type == Spells::FireBall {
-                println!("despawn spell fireball");
-                spawn_fireball_spell_collision(&mut commands, &fireburst_sprite, &transform, &impact_position);
-                commands.entity(entity).despawn_recursive();
-            }
-            else if cast_spell.spell_type == Spells::IceSpike {
-                println!("despawn spell icespike");
-                spawn_icespike_spell_collision(&mut commands, &icespikeshatter_sprite, &transform, &impact_position);
-                commands.entity(entity).despawn_recursive();
-            }
+            despawn_events.send(ProjectileDespawnEvent { projectile: entity, impact_position });
+        }
+    }
+}
+
+fn despawn_projectiles(
+    mut commands: Commands,
+    mut despawn_events: EventReader<ProjectileDespawnEvent>,
+    spell_query: Query<(&Transform, &CastSpell)>,
+    fireburst_sprite: Res<FireBurstSpriteAtlas>,
+    icespikeshatter_sprite: Res<IceSpikeShatterSpriteAtlas>,
+) {
+    let mut despawned = HashSet::new();
+    for event in despawn_events.read() {
+        if !despawned.insert(event.projectile) {
+            continue;
+        }
+        let Ok((transform, cast_spell)) = spell_query.get(event.projectile) else {
+            continue;
+        };
+        spawn_spell_impact(&mut commands, &fireburst_sprite, &icespikeshatter_sprite, cast_spell.spell_type, transform, &event.impact_position);
+        commands.entity(event.projectile).despawn_recursive();
+    }
+}
+
+fn spawn_spell_impact(
+    commands: &mut Commands,
+    fireburst_sprite: &Res<FireBurstSpriteAtlas>,
+    icespikeshatter_sprite: &Res<IceSpikeShatterSpriteAtlas>,
+    spell_type: Spells,
+    spell_transform: &Transform,
+    spell_impact_position: &Vec3,
+) {
+    match spell_type {
+        Spells::FireBall => spawn_fireball_spell_collision(commands, fireburst_sprite, spell_transform, spell_impact_position),
+        Spells::IceSpike => spawn_icespike_spell_collision(commands, icespikeshatter_sprite, spell_transform, spell_impact_position),
+    }
+}
+
+fn projectile_wall_collision(
+    mut collision_events: EventReader<FactionCollisionEvent>,
+    mut spell_query: Query<(&Transform, &mut ProjectileBehaviour)>,
+    mut despawn_events: EventWriter<ProjectileDespawnEvent>,
+) {
+    for event in collision_events.read() {
+        let hits_wall = event.is(Faction::Projectile, Faction::Wall) || event.is(Faction::EnemyProjectile, Faction::Wall);
+        if !event.started || !hits_wall {
+            continue;
+        }
+        let Ok((transform, mut behaviour)) = spell_query.get_mut(event.entity) else {
+            continue;
+        };
+
+        if behaviour.bounces_remaining > 0 {
+            behaviour.bounces_remaining -= 1;
+            continue;
+        }
+
+        despawn_events.send(ProjectileDespawnEvent { projectile: event.entity, impact_position: transform.translation });
+    }
+}
+
+fn orient_projectiles(
+    mut spell_query: Query<(&mut Transform, &mut CastSpell, &Velocity)>,
+) {
+    for (mut transform, mut cast_spell, velocity) in &mut spell_query {
+        let Some(direction) = velocity.linvel.try_normalize() else {
+            continue;
+        };
+        if direction.abs_diff_eq(cast_spell.direction, 0.001) {
+            continue;
         }
+        cast_spell.direction = direction;
+        transform.rotation = Quat::from_rotation_z(direction.y.atan2(direction.x) + PI);
     }
 }
 
@@ -326,53 +507,19 @@ pub fn spawn_icespike_attack(
     cursor_coord: &Res<CursorWorldCoordinates>,
     position_player: &Vec3,
 ) {
-    let animation_indices = AnimationIndices { first: 0, last: 44};
-    let sprite_head_offset = 29.0;
-
     let direction_vector_normalized = (cursor_coord.0.truncate() - position_player.truncate()).normalize();
     let sprite_spawn_position = position_player.truncate() + (direction_vector_normalized * 65.0);
-    let angle = direction_vector_normalized.angle_between(Vec2 { x: 1.0, y: 0.0 });
-
-    let spell_entity = commands.spawn((
-        SpriteSheetBundle {
-            texture: magic_sprite.image.clone(),
-            atlas: TextureAtlas {
-                layout: magic_sprite.layout.clone(),
-                index: animation_indices.first,
-            },
-            transform: Transform {
-                translation: Vec3::new(sprite_spawn_position.x, sprite_spawn_position.y, 1.0),
-                scale: Vec3::new(-SCALE/2.0, SCALE/2.0, 1.0),
-                rotation: Quat::from_rotation_z(-angle + PI),
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-        CastSpell {
-            spell_type: Spells::IceSpike,
-            _start_pos: sprite_spawn_position.extend(1.0),
-            direction: direction_vector_normalized,
-            velocity: 600.0,
-            collision_offset: sprite_head_offset
-        },
-        SpellFlightTime {timer: Timer::new(Duration::from_secs(1), TimerMode::Once)},
-        animation_indices.clone(),
-        AnimationTimer(Timer::from_seconds(0.05, TimerMode::Repeating)),
-        ActiveEvents::COLLISION_EVENTS,
-        Name::new("Ice Spike"),
-    )).id();
-
-    commands.entity(spell_entity).with_children(|parent| {
-        parent.spawn((
-            TransformBundle::from(Transform { translation: Vec3::new(26.0, 0.0, 0.0), ..Default::default()}),
-            RigidBody::Dynamic,
-            Collider::cuboid(5.0, 5.0),
-        )).insert(Velocity {
-            linvel: direction_vector_normalized,
-            angvel: 0.4
-        });
-    });
 
+    spawn_spell_projectile(
+        commands,
+        magic_sprite.image.clone(),
+        magic_sprite.layout.clone(),
+        Spells::IceSpike,
+        sprite_spawn_position,
+        direction_vector_normalized,
+        Spells::IceSpike.projectile_speed(),
+        Faction::Projectile,
+    );
 }
 
 pub fn spawn_fireball_attack(
@@ -381,53 +528,110 @@ pub fn spawn_fireball_attack(
     cursor_coord: &Res<CursorWorldCoordinates>,
     position_player: &Vec3,
 ) {
-    let animation_indices = AnimationIndices { first: 0, last: 44};
-    let sprite_head_offset = 18.0 * 2.0;
-
     let direction_vector_normalized = (cursor_coord.0.truncate() - position_player.truncate()).normalize();
     let sprite_spawn_position = position_player.truncate() + (direction_vector_normalized * 65.0);
-    let angle = direction_vector_normalized.angle_between(Vec2 { x: 1.0, y: 0.0 });
 
-    let mut spell_entity = commands.spawn((
+    spawn_spell_projectile(
+        commands,
+        magic_sprite.image.clone(),
+        magic_sprite.layout.clone(),
+        Spells::FireBall,
+        sprite_spawn_position,
+        direction_vector_normalized,
+        Spells::FireBall.projectile_speed(),
+        Faction::Projectile,
+    );
+}
+
+/// Fires a spell at the player on behalf of an enemy. It flies, bounces and shatters like the
+/// player's own spells but only collides with the player and walls.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_enemy_projectile(
+    commands: &mut Commands,
+    fireball_sprite: &FireBallSpriteAtlas,
+    icespike_sprite: &IceSpikeSpriteAtlas,
+    spell: Spells,
+    origin: Vec2,
+    direction: Vec2,
+    speed: f32,
+    damage: Damage,
+    knockback: f32,
+) {
+    let (texture, layout) = match spell {
+        Spells::FireBall => (fireball_sprite.image.clone(), fireball_sprite.layout.clone()),
+        Spells::IceSpike => (icespike_sprite.image.clone(), icespike_sprite.layout.clone()),
+    };
+    let projectile = spawn_spell_projectile(commands, texture, layout, spell, origin, direction, speed, Faction::EnemyProjectile);
+    commands.entity(projectile).insert(EnemyProjectile { damage, knockback });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_spell_projectile(
+    commands: &mut Commands,
+    texture: Handle<Image>,
+    layout: Handle<TextureAtlasLayout>,
+    spell: Spells,
+    origin: Vec2,
+    direction: Vec2,
+    speed: f32,
+    faction: Faction,
+) -> Entity {
+    let animation_indices = AnimationIndices { first: 0, last: 44};
+    let angle = direction.angle_between(Vec2 { x: 1.0, y: 0.0 });
+    let name = match spell {
+        Spells::FireBall => "FireBall",
+        Spells::IceSpike => "Ice Spike",
+    };
+
+    commands.spawn((
         SpriteSheetBundle {
-            texture: magic_sprite.image.clone(),
+            texture,
             atlas: TextureAtlas {
-                layout: magic_sprite.layout.clone(),
+                layout,
                 index: animation_indices.first,
             },
             transform: Transform {
-                translation: Vec3::new(sprite_spawn_position.x, sprite_spawn_position.y, 5.0),
+                translation: Vec3::new(origin.x, origin.y, 5.0),
                 scale: Vec3::new(-SCALE/2.0, SCALE/2.0, 1.0),
                 rotation: Quat::from_rotation_z(-angle + PI),
                 ..Default::default()
             },
             ..Default::default()
         },
-        CastSpell { 
-            spell_type: Spells::FireBall,
-            _start_pos: sprite_spawn_position.extend(1.0),
-            direction: direction_vector_normalized,
-            velocity: 300.0,
-            collision_offset: sprite_head_offset 
+        CastSpell {
+            spell_type: spell,
+            _start_pos: origin.extend(1.0),
+            direction,
+            velocity: speed,
+            collision_offset: spell.collision_offset(),
         },
         SpellFlightTime {timer: Timer::new(Duration::from_secs(1), TimerMode::Once)},
+        spell.projectile_behaviour(),
         animation_indices.clone(),
         AnimationTimer(Timer::from_seconds(0.05, TimerMode::Repeating)),
         ActiveEvents::COLLISION_EVENTS,
         LockedAxes::ROTATION_LOCKED,
-        Name::new("FireBall"),
+        Name::new(name),
         (
             RigidBody::Dynamic,
             Collider::cuboid(5.0, 5.0),
-            // TransformBundle::from(Transform { translation: Vec3::new(26.0, 0.0, 0.0), ..Default::default()}),
+            faction,
+            faction.collision_groups(),
+            faction.solver_groups(),
+            Restitution {
+                coefficient: 1.0,
+                combine_rule: CoefficientCombineRule::Max,
+            },
+            Friction {
+                coefficient: 0.0,
+                combine_rule: CoefficientCombineRule::Min,
+            },
         ),
         Velocity {
-            linvel: direction_vector_normalized * 300.0,
+            linvel: direction * speed,
             angvel: 0.0
         },
-    ));
-
-    spell_entity.insert(CollisionGroups::new(Group::from_bits(0b01).unwrap(), Group::from_bits(0b01).unwrap()));
+    )).id()
 }
 
 // fn spell_flight_system(
@@ -442,49 +646,93 @@ pub fn spawn_fireball_attack(
 // }
 
 fn enable_spell_cooldown(
-    mut spell_cooldown: ResMut<SpellCoolDown>,
+    mut spell_cooldowns: ResMut<SpellCoolDowns>,
     time: Res<Time>,
 ) {
-    spell_cooldown.timer.tick(time.delta());
+    for timer in spell_cooldowns.timers.values_mut() {
+        timer.tick(time.delta());
+    }
 }
 
 fn enemy_spell_collision_event(
-    mut collision_events: EventReader<CollisionEvent>,
-    query_name: Query<&Name, With<Collider>>,
+    mut collision_events: EventReader<FactionCollisionEvent>,
     mut events: EventWriter<EnemySpellCollisionEvent>
 ) {
     for event in collision_events.read() {
-        println!("Collision event detected: {:?}", event);
-        match event {
-            CollisionEvent::Started(entity_1, entity_2, _) => {
-                let unknown = &Name::new("Unknown");
-                let entity_1_name = query_name.get(*entity_1).unwrap_or(unknown);
-                let entity_2_name = query_name.get(*entity_2).unwrap_or(unknown);
-                println!("Entity 1 name: {:?}, Entity 2 name: {:?}", entity_1_name, entity_2_name);
-                if entity_1_name == &Name::new("FireBall") && entity_2_name == &Name::new("Enemy") {
-                    events.send(EnemySpellCollisionEvent { 
-                        spell_entity: *entity_1, 
-                        enemy_entity: *entity_2
-                    });
-                }
-                else if entity_1_name == &Name::new("Enemy") && entity_2_name == &Name::new("FireBall") {
-                    events.send(EnemySpellCollisionEvent { 
-                        spell_entity: *entity_2, 
-                        enemy_entity: *entity_1
-                    });
-                }
-            },
-            _ => {},
+        if event.started && event.is(Faction::Projectile, Faction::Enemy) {
+            events.send(EnemySpellCollisionEvent {
+                spell_entity: event.entity,
+                enemy_entity: event.other,
+            });
         }
     }
 }
 
 fn receive_enemy_spell_collision_event(
     mut events: EventReader<EnemySpellCollisionEvent>,
-    mut commands: Commands,
+    mut spell_query: Query<(&Transform, &CastSpell, &mut ProjectileBehaviour)>,
+    mut enemy_query: Query<&mut StatusEffects>,
+    mut hit_events: EventWriter<HitEvent>,
+    mut despawn_events: EventWriter<ProjectileDespawnEvent>,
+) {
+    for event in events.read() {
+        let Ok((spell_transform, cast_spell, mut behaviour)) = spell_query.get_mut(event.spell_entity) else {
+            continue;
+        };
+        if behaviour.pierces_remaining > 0 {
+            behaviour.pierces_remaining -= 1;
+        } else {
+            despawn_events.send(ProjectileDespawnEvent { projectile: event.spell_entity, impact_position: spell_transform.translation });
+        }
+
+        let Ok(mut status_effects) = enemy_query.get_mut(event.enemy_entity) else {
+            continue;
+        };
+
+        let (damage, knockback) = match cast_spell.spell_type {
+            Spells::FireBall => {
+                status_effects.apply(StatusEffect::burn());
+                (Damage::new(10.0, DamageType::Fire).with_crit(0.05, 1.5), 150.0)
+            },
+            Spells::IceSpike => {
+                if status_effects.has(StatusEffectKind::Slow) {
+                    status_effects.apply(StatusEffect::freeze());
+                }
+                status_effects.apply(StatusEffect::slow());
+                (Damage::new(6.0, DamageType::Ice).with_crit(0.2, 2.0), 80.0)
+            },
+        };
+        hit_events.send(HitEvent {
+            target: event.enemy_entity,
+            source_position: spell_transform.translation,
+            damage,
+            knockback,
+            stun: 0.2,
+        });
+    }
+}
+
+fn enemy_projectile_player_collision(
+    mut collision_events: EventReader<FactionCollisionEvent>,
+    projectile_query: Query<(&Transform, &EnemyProjectile)>,
+    mut hit_events: EventWriter<HitEvent>,
+    mut despawn_events: EventWriter<ProjectileDespawnEvent>,
 ) {
-    for event in events.read().enumerate() {
-        commands.entity(event.1.enemy_entity).despawn_recursive();
-        commands.entity(event.1.spell_entity).despawn_recursive();
+    for event in collision_events.read() {
+        if !event.started || !event.is(Faction::EnemyProjectile, Faction::Player) {
+            continue;
+        }
+        let Ok((transform, projectile)) = projectile_query.get(event.entity) else {
+            continue;
+        };
+
+        hit_events.send(HitEvent {
+            target: event.other,
+            source_position: transform.translation,
+            damage: projectile.damage,
+            knockback: projectile.knockback,
+            stun: 0.2,
+        });
+        despawn_events.send(ProjectileDespawnEvent { projectile: event.entity, impact_position: transform.translation });
     }
-}
\ No newline at end of file
+}
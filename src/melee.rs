@@ -1,16 +1,80 @@
 use std::f32::consts::PI;
 use bevy_rapier2d::prelude::*;
 
-use bevy::{prelude::*, tasks::futures_lite::io::Repeat};
+use bevy::prelude::*;
 
-use crate::{game::Equipment, player::ControllablePlayer, spritesheet::{get_sprite_atlas_layout, get_sprite_texture_handle, SpriteCollection, TextureAtlases, WEAPON_SWORD}, AppState, CursorWorldCoordinates, SCALE};
+use crate::{collision::Faction, combat::HitEvent, damage::{Damage, DamageType}, enemy::Enemy, game::{Equipment, OnGameScreen}, player::{ControllablePlayer, PlayerDead}, spritesheet::{get_sprite_atlas_layout, get_sprite_texture_handle, SpriteCollection, TextureAtlases, WEAPON_SWORD}, AppState, CursorWorldCoordinates, SCALE};
 
 pub struct MeleePlugin;
 
+const SWORD_DISTANCE: f32 = 60.0;
+const COMBO_WINDOW: f32 = 0.6;
+const MAX_COMBO_LENGTH: usize = 3;
+const MELEE_CRIT_MULTIPLIER: f32 = 2.0;
+
 impl Plugin for MeleePlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(AppState::InGame), setup);
-        app.add_systems(Update, (sword_follow_cursor, sword_swing, update_swing, sword_stab_animation).run_if(in_state(AppState::InGame)));
+        app.add_systems(Update, (sword_swing, update_swing, sword_follow_cursor, sword_hit_detection).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MeleeAttackKind {
+    Light,
+    Heavy,
+}
+
+impl MeleeAttackKind {
+    /// Total rotation of the swing in radians.
+    fn arc(&self) -> f32 {
+        match self {
+            MeleeAttackKind::Light => PI * 0.6,
+            MeleeAttackKind::Heavy => PI,
+        }
+    }
+
+    fn duration(&self) -> f32 {
+        match self {
+            MeleeAttackKind::Light => 0.2,
+            MeleeAttackKind::Heavy => 0.4,
+        }
+    }
+
+    fn damage(&self) -> f32 {
+        match self {
+            MeleeAttackKind::Light => 5.0,
+            MeleeAttackKind::Heavy => 12.0,
+        }
+    }
+
+    fn knockback(&self) -> f32 {
+        match self {
+            MeleeAttackKind::Light => 120.0,
+            MeleeAttackKind::Heavy => 320.0,
+        }
+    }
+
+    fn crit_chance(&self) -> f32 {
+        match self {
+            MeleeAttackKind::Light => 0.1,
+            MeleeAttackKind::Heavy => 0.2,
+        }
+    }
+
+    fn stun(&self) -> f32 {
+        match self {
+            MeleeAttackKind::Light => 0.15,
+            MeleeAttackKind::Heavy => 0.4,
+        }
+    }
+
+    /// Portion of the swing, as a fraction of its duration, during which the hitbox is live.
+    fn active_window(&self) -> (f32, f32) {
+        match self {
+            MeleeAttackKind::Light => (0.15, 0.85),
+            MeleeAttackKind::Heavy => (0.3, 0.9),
+        }
     }
 }
 
@@ -24,51 +88,186 @@ enum SwordState {
 struct SwordSwing {
     timer: Timer,
     swinging: bool,
-    start_rotation: f32,
-    end_rotation: f32,
+    kind: MeleeAttackKind,
+    aim_angle: f32,
+    start_offset: f32,
+    end_offset: f32,
+    damage: f32,
+    hit_entities: Vec<Entity>,
 }
 
 impl SwordSwing {
-    fn new(duration: f32, start_rotation: f32,  end_rotation: f32) -> Self {
-        SwordSwing { 
-            timer: Timer::from_seconds(duration, TimerMode::Once), 
-            swinging: false, 
-            start_rotation, 
-            end_rotation 
+    fn new() -> Self {
+        SwordSwing {
+            timer: Timer::from_seconds(MeleeAttackKind::Light.duration(), TimerMode::Once),
+            swinging: false,
+            kind: MeleeAttackKind::Light,
+            aim_angle: 0.0,
+            start_offset: 0.0,
+            end_offset: 0.0,
+            damage: 0.0,
+            hit_entities: Vec::new(),
         }
     }
+
+    fn hitbox_active(&self) -> bool {
+        let (start, end) = self.kind.active_window();
+        let progress = self.timer.fraction();
+        self.swinging && progress >= start && progress <= end
+    }
+}
+
+/// Attacks chained together within `COMBO_WINDOW` of each other. Each link adds damage,
+/// and a heavy attack ending a chain of lights is a finisher that resets the combo.
+#[derive(Component)]
+struct MeleeCombo {
+    chain: Vec<MeleeAttackKind>,
+    window: Timer,
+}
+
+impl Default for MeleeCombo {
+    fn default() -> Self {
+        MeleeCombo {
+            chain: Vec::new(),
+            window: Timer::from_seconds(COMBO_WINDOW, TimerMode::Once),
+        }
+    }
+}
+
+fn aim_angle(player_position: Vec3, cursor_position: Vec3) -> f32 {
+    let direction = cursor_position.truncate() - player_position.truncate();
+    direction.y.atan2(direction.x)
 }
 
 fn update_swing(
+    mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(&mut SwordSwing, &mut Transform)>,
+    mut query: Query<(Entity, &mut SwordSwing, &mut SwordState, &mut Transform), With<Equipment>>,
+    player_query: Query<&Transform, (With<ControllablePlayer>, Without<Equipment>)>,
 ) {
-    for (mut swing, mut transform) in query.iter_mut() {
-        if swing.swinging {
-            swing.timer.tick(time.delta());
-            let progress = swing.timer.fraction();
-            let current_rotation = transform.rotation;
-            let target_rotation = Quat::from_rotation_z(swing.start_rotation + progress * (swing.end_rotation - swing.start_rotation));
-            transform.rotation = current_rotation.slerp(target_rotation, 0.5); // Adjust the slerp factor as needed
-
-            if swing.timer.finished() {
-                swing.swinging = false;
-            }
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    for (entity, mut swing, mut state, mut transform) in query.iter_mut() {
+        if !swing.swinging {
+            continue;
+        }
+
+        swing.timer.tick(time.delta());
+        let progress = swing.timer.fraction();
+        let angle = swing.aim_angle + swing.start_offset + progress * (swing.end_offset - swing.start_offset);
+        let offset = Vec2::new(angle.cos(), angle.sin()) * SWORD_DISTANCE;
+        transform.translation.x = player_transform.translation.x + offset.x;
+        transform.translation.y = player_transform.translation.y + offset.y;
+        transform.rotation = Quat::from_rotation_z(angle - PI/2.0);
+
+        if swing.hitbox_active() {
+            commands.entity(entity).remove::<ColliderDisabled>();
+        } else {
+            commands.entity(entity).insert(ColliderDisabled);
+        }
+
+        if swing.timer.finished() {
+            swing.swinging = false;
+            *state = SwordState::IDLE;
         }
     }
 }
 
 fn sword_swing(
+    time: Res<Time>,
     keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&mut SwordSwing, &mut Transform)>,
+    cursor_position: Res<CursorWorldCoordinates>,
+    mut query: Query<(&mut SwordSwing, &mut SwordState, &mut MeleeCombo)>,
+    player_query: Query<&Transform, (With<ControllablePlayer>, Without<PlayerDead>)>,
 ) {
-    for (mut swing, mut transform) in &mut query {
-        if keyboard_input.just_pressed(KeyCode::Space) && !swing.swinging {
-            println!("Started swing");
-            swing.swinging = true;
-            swing.timer.reset();
-            swing.start_rotation = transform.rotation.z;
-            transform.rotation = Quat::from_rotation_z(swing.start_rotation);
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    for (mut swing, mut state, mut combo) in &mut query {
+        combo.window.tick(time.delta());
+        if combo.window.finished() && !swing.swinging {
+            combo.chain.clear();
+        }
+
+        let kind = if keyboard_input.just_pressed(KeyCode::Space) {
+            MeleeAttackKind::Light
+        } else if keyboard_input.just_pressed(KeyCode::KeyF) {
+            MeleeAttackKind::Heavy
+        } else {
+            continue;
+        };
+        if swing.swinging {
+            continue;
+        }
+
+        if combo.chain.len() >= MAX_COMBO_LENGTH {
+            combo.chain.clear();
+        }
+        let is_finisher = kind == MeleeAttackKind::Heavy && !combo.chain.is_empty();
+        combo.chain.push(kind);
+        let combo_multiplier = 1.0 + 0.25 * (combo.chain.len() - 1) as f32;
+
+        // Alternate swing direction on each link so chained attacks sweep back and forth
+        let direction = if combo.chain.len() % 2 == 0 { -1.0 } else { 1.0 };
+        let half_arc = kind.arc() / 2.0;
+
+        println!("Started {:?} swing, combo length {}", kind, combo.chain.len());
+        swing.kind = kind;
+        swing.aim_angle = aim_angle(player_transform.translation, cursor_position.0);
+        swing.start_offset = half_arc * direction;
+        swing.end_offset = -half_arc * direction;
+        swing.damage = kind.damage() * combo_multiplier * if is_finisher { 2.0 } else { 1.0 };
+        swing.hit_entities.clear();
+        swing.timer = Timer::from_seconds(kind.duration(), TimerMode::Once);
+        swing.swinging = true;
+        *state = SwordState::ATTACK;
+
+        if is_finisher {
+            combo.chain.clear();
+        }
+        combo.window.reset();
+    }
+}
+
+fn sword_hit_detection(
+    rapier_context: Res<RapierContext>,
+    mut sword_query: Query<(Entity, &mut SwordSwing)>,
+    enemy_query: Query<Entity, With<Enemy>>,
+    player_query: Query<&Transform, With<ControllablePlayer>>,
+    mut hit_events: EventWriter<HitEvent>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    for (sword_entity, mut swing) in &mut sword_query {
+        if !swing.hitbox_active() {
+            continue;
+        }
+
+        for (collider_1, collider_2, intersecting) in rapier_context.intersection_pairs_with(sword_entity) {
+            if !intersecting {
+                continue;
+            }
+            let other = if collider_1 == sword_entity { collider_2 } else { collider_1 };
+            if swing.hit_entities.contains(&other) {
+                continue;
+            }
+            if !enemy_query.contains(other) {
+                continue;
+            }
+
+            swing.hit_entities.push(other);
+            hit_events.send(HitEvent {
+                target: other,
+                source_position: player_transform.translation,
+                damage: Damage::new(swing.damage, DamageType::Physical).with_crit(swing.kind.crit_chance(), MELEE_CRIT_MULTIPLIER),
+                knockback: swing.kind.knockback(),
+                stun: swing.kind.stun(),
+            });
         }
     }
 }
@@ -81,14 +280,14 @@ fn setup(
     let requested_sprite = WEAPON_SWORD.to_owned();
 
     let sword_sprite_texture = get_sprite_texture_handle(
-        requested_sprite.clone(), 
-        &texture_atlas, 
+        requested_sprite.clone(),
+        &texture_atlas,
         &sprite_collection
     ).expect("Could not load sword texture handle");
 
     let sword_sprite_atlas_layout = get_sprite_atlas_layout(
         requested_sprite.clone(),
-        &texture_atlas, 
+        &texture_atlas,
         &sprite_collection
     ).expect("Could not load sword texture atlas layout");
 
@@ -113,46 +312,37 @@ fn setup(
         Name::new("Sword"),
         Equipment,
         Collider::cuboid(5.0, 8.0),
+        Faction::PlayerMelee,
+        Faction::PlayerMelee.collision_groups(),
+        ColliderDisabled,
         SwordState::IDLE,
-        SwordSwing::new(0.2, 0.0, PI/2.0),
+        SwordSwing::new(),
+        MeleeCombo::default(),
         Sensor,
+        OnGameScreen,
     ));
 }
 
-fn sword_stab_animation(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut query: Query<(&mut SwordSwing, &mut Transform)>,
-) {
-
-}
-
 fn sword_follow_cursor(
-    mut sword_transform: Query<(&mut Transform, &mut SwordSwing), With<Equipment>>,
+    mut sword_transform: Query<(&mut Transform, &SwordSwing), With<Equipment>>,
     player_transform: Query<&Transform, (With<ControllablePlayer>, Without<Equipment>)>,
     cursor_position: Res<CursorWorldCoordinates>,
 ) {
-    let distance_from_player: f32 = 60.0;
+    let Ok(player_translation) = player_transform.get_single() else {
+        return;
+    };
+
     for (mut sword_translation, swing) in sword_transform.iter_mut() {
-        if !swing.swinging {
-            if let Ok(player_translation) = player_transform.get_single() {
-                let direction_vector_normalized = (cursor_position.0.truncate() - player_translation.translation.truncate()).normalize();
-                sword_translation.translation.x = player_translation.translation.x + (direction_vector_normalized.x * distance_from_player);
-                sword_translation.translation.y = player_translation.translation.y + (direction_vector_normalized.y * distance_from_player);
-                let angle = direction_vector_normalized.angle_between(Vec2 { x: 1.0, y: 0.0 });
-    
-                if angle <= 0.0 && angle >= -PI/2.0 {
-                    sword_translation.rotation = Quat::from_rotation_z(-angle - PI/4.0);
-                }
-                if angle <= -PI/2.0 && angle >= -PI {
-                    sword_translation.rotation = Quat::from_rotation_z(-angle + PI + PI/4.0);
-                }
-                if angle > 0.0 && angle <= PI/2.0 {
-                    sword_translation.rotation = Quat::from_rotation_z(-angle - PI/4.0);
-                }
-                if angle > PI/2.0 && angle <= PI {
-                    sword_translation.rotation = Quat::from_rotation_z(-angle + PI + PI/4.0);
-                }
-            }
+        if swing.swinging {
+            continue;
         }
+
+        let angle = aim_angle(player_translation.translation, cursor_position.0);
+        sword_translation.translation.x = player_translation.translation.x + (angle.cos() * SWORD_DISTANCE);
+        sword_translation.translation.y = player_translation.translation.y + (angle.sin() * SWORD_DISTANCE);
+
+        // Lean the blade away from the player, mirrored on the left side
+        let tilt = if angle.abs() <= PI/2.0 { PI/4.0 } else { -PI/4.0 };
+        sword_translation.rotation = Quat::from_rotation_z(angle - PI/2.0 + tilt);
     }
-}
\ No newline at end of file
+}
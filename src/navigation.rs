@@ -0,0 +1,481 @@
+use std::{cmp::Ordering, collections::{BinaryHeap, HashMap, HashSet}};
+
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    behaviour::EnemyBehaviour,
+    enemy::Enemy,
+    level::{collision_layer, Wall},
+    player::{ControllablePlayer, PlayerDead},
+    AppState,
+};
+
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+/// Upper bound on expanded nodes so an unreachable goal cannot stall a frame.
+const MAX_SEARCH_NODES: usize = 4000;
+const REPATH_SECONDS: f32 = 0.5;
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(NavigationGrids::default());
+        app.add_systems(Update, (build_navigation_grids, remove_unloaded_navigation_grids));
+        app.add_systems(Update, (add_navigation_paths, update_navigation_paths).chain().run_if(in_state(AppState::InGame)));
+        // Obstacles inside levels only have their world position after propagation
+        app.add_systems(PostUpdate, update_navigation_obstacles.after(TransformSystem::TransformPropagate).run_if(in_state(AppState::InGame)));
+    }
+}
+
+/// Walkable cells of one LDtk level, built from its `Wall` IntGrid cells.
+#[derive(Debug, Clone)]
+pub struct NavigationGrid {
+    pub width: i32,
+    pub height: i32,
+    /// World position of the bottom-left corner of the level.
+    pub origin: Vec2,
+    /// Size of one cell in world units.
+    pub cell_size: f32,
+    walls: HashSet<IVec2>,
+    /// Cells covered by closed doors and other obstacles that can open up again,
+    /// counted per obstacle so overlapping obstacles do not unblock each other.
+    blocked: HashMap<IVec2, u32>,
+    /// The cells each obstacle currently holds blocked.
+    obstacles: HashMap<Entity, Vec<IVec2>>,
+}
+
+impl NavigationGrid {
+    pub fn new(width: i32, height: i32, origin: Vec2, cell_size: f32) -> Self {
+        NavigationGrid { width, height, origin, cell_size, walls: HashSet::new(), blocked: HashMap::new(), obstacles: HashMap::new() }
+    }
+
+    pub fn add_wall(&mut self, cell: IVec2) {
+        self.walls.insert(cell);
+    }
+
+    /// Blocks the cells from `min` to `max` on behalf of `obstacle`, replacing whatever it blocked before.
+    pub fn block(&mut self, obstacle: Entity, min: IVec2, max: IVec2) {
+        self.unblock(obstacle);
+        let cells: Vec<IVec2> = (min.y..=max.y)
+            .flat_map(|y| (min.x..=max.x).map(move |x| IVec2::new(x, y)))
+            .collect();
+        for cell in &cells {
+            *self.blocked.entry(*cell).or_insert(0) += 1;
+        }
+        self.obstacles.insert(obstacle, cells);
+    }
+
+    /// Releases the cells held by `obstacle`, returning false if it held none.
+    /// Cells another obstacle also covers stay blocked.
+    pub fn unblock(&mut self, obstacle: Entity) -> bool {
+        let Some(cells) = self.obstacles.remove(&obstacle) else {
+            return false;
+        };
+        for cell in cells {
+            if let Some(count) = self.blocked.get_mut(&cell) {
+                *count -= 1;
+                if *count == 0 {
+                    self.blocked.remove(&cell);
+                }
+            }
+        }
+        true
+    }
+
+    pub fn is_blocked_by(&self, obstacle: Entity) -> bool {
+        self.obstacles.contains_key(&obstacle)
+    }
+
+    pub fn contains(&self, cell: IVec2) -> bool {
+        cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.height
+    }
+
+    pub fn is_walkable(&self, cell: IVec2) -> bool {
+        self.contains(cell) && !self.walls.contains(&cell) && !self.blocked.contains_key(&cell)
+    }
+
+    pub fn world_to_cell(&self, position: Vec2) -> IVec2 {
+        ((position - self.origin) / self.cell_size).floor().as_ivec2()
+    }
+
+    pub fn cell_to_world(&self, cell: IVec2) -> Vec2 {
+        self.origin + (cell.as_vec2() + Vec2::splat(0.5)) * self.cell_size
+    }
+
+    /// True when no wall cell lies on the straight segment between two world positions.
+    pub fn has_line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        // Walk every cell the segment passes through (Amanatides & Woo)
+        let start = (from - self.origin) / self.cell_size;
+        let end = (to - self.origin) / self.cell_size;
+        let mut cell = start.floor().as_ivec2();
+        let end_cell = end.floor().as_ivec2();
+        let delta = end - start;
+        let step = IVec2::new(delta.x.signum() as i32, delta.y.signum() as i32);
+
+        let boundary_distance = |position: f32, cell: i32, step: i32, delta: f32| {
+            if step == 0 {
+                f32::INFINITY
+            } else {
+                let boundary = if step > 0 { cell as f32 + 1.0 } else { cell as f32 };
+                (boundary - position) / delta
+            }
+        };
+        let mut t_max = Vec2::new(
+            boundary_distance(start.x, cell.x, step.x, delta.x),
+            boundary_distance(start.y, cell.y, step.y, delta.y),
+        );
+        let t_delta = Vec2::new(
+            if step.x == 0 { f32::INFINITY } else { 1.0 / delta.x.abs() },
+            if step.y == 0 { f32::INFINITY } else { 1.0 / delta.y.abs() },
+        );
+
+        loop {
+            if !self.is_walkable(cell) {
+                return false;
+            }
+            if cell == end_cell || (t_max.x > 1.0 && t_max.y > 1.0) {
+                return true;
+            }
+            if t_max.x < t_max.y {
+                cell.x += step.x;
+                t_max.x += t_delta.x;
+            } else {
+                cell.y += step.y;
+                t_max.y += t_delta.y;
+            }
+        }
+    }
+
+    /// A* over the grid with 8-way movement. Diagonal steps may not cut wall corners.
+    /// Returns the cells from start to goal inclusive.
+    pub fn find_path(&self, start: IVec2, goal: IVec2) -> Option<Vec<IVec2>> {
+        if !self.is_walkable(start) || !self.is_walkable(goal) {
+            return None;
+        }
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<IVec2, IVec2> = HashMap::new();
+        let mut cost_so_far: HashMap<IVec2, u32> = HashMap::new();
+        open.push(OpenNode { cell: start, priority: octile_distance(start, goal) });
+        cost_so_far.insert(start, 0);
+
+        let mut expanded = 0;
+        while let Some(OpenNode { cell, .. }) = open.pop() {
+            if cell == goal {
+                let mut path = vec![goal];
+                let mut current = goal;
+                while let Some(&previous) = came_from.get(&current) {
+                    path.push(previous);
+                    current = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            expanded += 1;
+            if expanded > MAX_SEARCH_NODES {
+                return None;
+            }
+
+            let current_cost = cost_so_far[&cell];
+            for (offset, step_cost) in NEIGHBOURS {
+                let next = cell + offset;
+                if !self.is_walkable(next) {
+                    continue;
+                }
+                if offset.x != 0 && offset.y != 0
+                        && (!self.is_walkable(cell + IVec2::new(offset.x, 0)) || !self.is_walkable(cell + IVec2::new(0, offset.y))) {
+                    continue;
+                }
+
+                let next_cost = current_cost + step_cost;
+                if cost_so_far.get(&next).map_or(true, |&cost| next_cost < cost) {
+                    cost_so_far.insert(next, next_cost);
+                    came_from.insert(next, cell);
+                    open.push(OpenNode { cell: next, priority: next_cost + octile_distance(next, goal) });
+                }
+            }
+        }
+        None
+    }
+
+    /// Finds a path between world positions and removes waypoints that can be skipped
+    /// with a straight line, returning world positions excluding the start.
+    pub fn find_smoothed_path(&self, from: Vec2, to: Vec2) -> Option<Vec<Vec2>> {
+        let cells = self.find_path(self.world_to_cell(from), self.world_to_cell(to))?;
+        let mut points: Vec<Vec2> = cells.iter().map(|&cell| self.cell_to_world(cell)).collect();
+        if let Some(last) = points.last_mut() {
+            *last = to;
+        }
+
+        let mut smoothed = Vec::new();
+        let mut anchor = from;
+        let mut index = 0;
+        while index < points.len() {
+            // Skip ahead to the furthest point still visible from the anchor
+            let mut furthest = index;
+            for candidate in (index..points.len()).rev() {
+                if self.has_line_of_sight(anchor, points[candidate]) {
+                    furthest = candidate;
+                    break;
+                }
+            }
+            anchor = points[furthest];
+            smoothed.push(anchor);
+            index = furthest + 1;
+        }
+        Some(smoothed)
+    }
+}
+
+const NEIGHBOURS: [(IVec2, u32); 8] = [
+    (IVec2::new(1, 0), STRAIGHT_COST),
+    (IVec2::new(-1, 0), STRAIGHT_COST),
+    (IVec2::new(0, 1), STRAIGHT_COST),
+    (IVec2::new(0, -1), STRAIGHT_COST),
+    (IVec2::new(1, 1), DIAGONAL_COST),
+    (IVec2::new(1, -1), DIAGONAL_COST),
+    (IVec2::new(-1, 1), DIAGONAL_COST),
+    (IVec2::new(-1, -1), DIAGONAL_COST),
+];
+
+fn octile_distance(a: IVec2, b: IVec2) -> u32 {
+    let difference = (a - b).abs();
+    let straight = difference.x.max(difference.y) - difference.x.min(difference.y);
+    let diagonal = difference.x.min(difference.y);
+    straight as u32 * STRAIGHT_COST + diagonal as u32 * DIAGONAL_COST
+}
+
+#[derive(PartialEq, Eq)]
+struct OpenNode {
+    cell: IVec2,
+    priority: u32,
+}
+
+impl Ord for OpenNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Reversed so the BinaryHeap pops the lowest priority first
+        other.priority.cmp(&self.priority)
+            .then_with(|| (self.cell.x, self.cell.y).cmp(&(other.cell.x, other.cell.y)))
+    }
+}
+
+impl PartialOrd for OpenNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Navigation grids for every loaded level, keyed by level entity.
+#[derive(Resource, Default)]
+pub struct NavigationGrids {
+    pub grids: HashMap<Entity, NavigationGrid>,
+}
+
+impl NavigationGrids {
+    pub fn grid_at(&self, position: Vec2) -> Option<&NavigationGrid> {
+        self.grids.values().find(|grid| grid.contains(grid.world_to_cell(position)))
+    }
+}
+
+/// A collider that opens and closes, like a door. Its cells are unwalkable while the collider is enabled.
+#[derive(Component, Debug)]
+pub struct NavigationObstacle {
+    /// In world units.
+    pub size: Vec2,
+}
+
+impl NavigationObstacle {
+    pub fn new(size: Vec2) -> Self {
+        NavigationObstacle { size }
+    }
+}
+
+/// Cached path for an enemy. Only recomputed when the goal moves to a different cell.
+#[derive(Component)]
+pub struct NavigationPath {
+    pub waypoints: Vec<Vec2>,
+    goal_cell: Option<IVec2>,
+    repath_timer: Timer,
+}
+
+impl Default for NavigationPath {
+    fn default() -> Self {
+        NavigationPath {
+            waypoints: Vec::new(),
+            goal_cell: None,
+            repath_timer: Timer::from_seconds(REPATH_SECONDS, TimerMode::Once),
+        }
+    }
+}
+
+impl NavigationPath {
+    pub fn next_waypoint(&self) -> Option<Vec2> {
+        self.waypoints.first().copied()
+    }
+
+    pub fn clear(&mut self) {
+        self.waypoints.clear();
+        self.goal_cell = None;
+    }
+}
+
+fn build_navigation_grids(
+    mut navigation_grids: ResMut<NavigationGrids>,
+    wall_query: Query<(&GridCoords, &Parent), Added<Wall>>,
+    parent_query: Query<&Parent, Without<Wall>>,
+    level_query: Query<(&LevelIid, &Transform, &Parent)>,
+    ldtk_projects: Query<(&Handle<LdtkProject>, &Transform)>,
+    ldtk_project_assets: Res<Assets<LdtkProject>>,
+) {
+    for (grid_coords, parent) in &wall_query {
+        // Same hierarchy as in spawn_wall_collision: tile -> layer -> level
+        let Ok(grandparent) = parent_query.get(parent.get()) else {
+            continue;
+        };
+        let level_entity = grandparent.get();
+
+        if !navigation_grids.grids.contains_key(&level_entity) {
+            let Ok((level_iid, level_transform, world)) = level_query.get(level_entity) else {
+                continue;
+            };
+            // Global transforms are not propagated yet on the frame the level spawns,
+            // so place the level using the transform of the world that owns it directly
+            let Ok((ldtk_handle, world_transform)) = ldtk_projects.get(world.get()) else {
+                continue;
+            };
+            let Some(ldtk_project) = ldtk_project_assets.get(ldtk_handle) else {
+                continue;
+            };
+            let Some(level) = ldtk_project.as_standalone().get_loaded_level_by_iid(&level_iid.to_string()) else {
+                continue;
+            };
+            let Some(&LayerInstance { c_wid: width, c_hei: height, grid_size, .. }) = collision_layer(&level) else {
+                continue;
+            };
+            let origin = world_transform.transform_point(level_transform.translation).truncate();
+
+            println!("Building navigation grid {}x{} for level {}", width, height, level_iid);
+            navigation_grids.grids.insert(level_entity, NavigationGrid::new(
+                width,
+                height,
+                origin,
+                grid_size as f32 * world_transform.scale.x,
+            ));
+        }
+
+        if let Some(grid) = navigation_grids.grids.get_mut(&level_entity) {
+            grid.add_wall(IVec2::new(grid_coords.x, grid_coords.y));
+        }
+    }
+}
+
+fn remove_unloaded_navigation_grids(
+    mut navigation_grids: ResMut<NavigationGrids>,
+    mut removed_levels: RemovedComponents<LevelIid>,
+) {
+    for level_entity in removed_levels.read() {
+        navigation_grids.grids.remove(&level_entity);
+    }
+}
+
+fn add_navigation_paths(
+    mut commands: Commands,
+    enemy_query: Query<Entity, (With<EnemyBehaviour>, Without<NavigationPath>)>,
+) {
+    for entity in &enemy_query {
+        commands.entity(entity).insert(NavigationPath::default());
+    }
+}
+
+fn update_navigation_paths(
+    time: Res<Time>,
+    navigation_grids: Res<NavigationGrids>,
+    mut enemy_query: Query<(&Transform, &EnemyBehaviour, &mut NavigationPath), With<Enemy>>,
+    player_query: Query<&Transform, (With<ControllablePlayer>, Without<PlayerDead>, Without<Enemy>)>,
+) {
+    let player_position = player_query.get_single().ok().map(|transform| transform.translation.truncate());
+
+    for (transform, behaviour, mut path) in &mut enemy_query {
+        let position = transform.translation.truncate();
+        path.repath_timer.tick(time.delta());
+
+        let (Some(destination), Some(grid)) = (behaviour.destination(player_position), navigation_grids.grid_at(position)) else {
+            path.clear();
+            continue;
+        };
+
+        // Drop waypoints as they are reached
+        while path.next_waypoint().is_some_and(|waypoint| waypoint.distance(position) <= grid.cell_size * 0.25) {
+            path.waypoints.remove(0);
+        }
+
+        let goal_cell = grid.world_to_cell(destination);
+        let goal_moved = path.goal_cell != Some(goal_cell);
+        if !goal_moved && !path.waypoints.is_empty() {
+            continue;
+        }
+        if !path.repath_timer.finished() && path.goal_cell.is_some() {
+            continue;
+        }
+
+        path.repath_timer.reset();
+        path.goal_cell = Some(goal_cell);
+        path.waypoints = if grid.has_line_of_sight(position, destination) {
+            vec![destination]
+        } else {
+            grid.find_smoothed_path(position, destination).unwrap_or_default()
+        };
+    }
+}
+
+/// Keeps the grid cells under doors in sync with their colliders, and sends enemies looking for a new
+/// path whenever a door opens or closes. Obstacles whose level has no grid yet are retried every frame,
+/// and a rebuilt grid is blocked again from scratch.
+fn update_navigation_obstacles(
+    mut navigation_grids: ResMut<NavigationGrids>,
+    obstacle_query: Query<(Entity, &GlobalTransform, &NavigationObstacle, Has<ColliderDisabled>)>,
+    mut removed_obstacles: RemovedComponents<NavigationObstacle>,
+    mut path_query: Query<&mut NavigationPath>,
+) {
+    let mut changed = false;
+    for (entity, global_transform, obstacle, disabled) in &obstacle_query {
+        let blocked = !disabled;
+        let centre = global_transform.translation().truncate();
+        let Some(grid) = navigation_grids.grids.values_mut()
+            .find(|grid| grid.contains(grid.world_to_cell(centre))) else {
+            continue;
+        };
+        if grid.is_blocked_by(entity) == blocked {
+            continue;
+        }
+
+        if blocked {
+            // Shrink the rectangle a little so an obstacle exactly one cell wide does not spill into its neighbours
+            let half_size = obstacle.size / 2.0 - Vec2::splat(0.1);
+            let min = grid.world_to_cell(centre - half_size);
+            let max = grid.world_to_cell(centre + half_size);
+            grid.block(entity, min, max);
+        } else {
+            grid.unblock(entity);
+        }
+        changed = true;
+    }
+
+    // Doors despawned while closed must not leave their cells blocked
+    for entity in removed_obstacles.read() {
+        for grid in navigation_grids.grids.values_mut() {
+            changed |= grid.unblock(entity);
+        }
+    }
+
+    if changed {
+        for mut path in &mut path_query {
+            path.clear();
+        }
+    }
+}
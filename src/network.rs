@@ -74,6 +74,12 @@ pub struct ClientLobby {
     players: HashMap<ClientId, PlayerInfo>
 }
 
+impl ClientLobby {
+    pub fn player_count(&self) -> usize {
+        self.players.len()
+    }
+}
+
 #[derive(Debug)]
 pub struct PlayerInfo {
     client_entity: Entity,
@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 use bevy_rapier2d::{prelude::*, rapier::dynamics::{RigidBodyForces, RigidBodyVelocity}};
-use crate::{enemy::Enemy, game::{AnimationTimer, Equipment}, spritesheet::*, AppState, CursorWorldCoordinates, PlayerPosition, SCALE};
+use crate::{collision::Faction, enemy::Enemy, game::{AnimationTimer, Equipment, OnGameScreen}, spritesheet::*, AppState, CursorWorldCoordinates, PlayerPosition, SCALE};
 
 pub struct PlayerPlugin;
 
@@ -57,6 +57,14 @@ pub struct PlayerSpriteAnimationStates {
 #[derive(Component)]
 pub struct ControllablePlayer;
 
+#[derive(Component)]
+pub struct PlayerDead;
+
+#[derive(Event)]
+pub struct PlayerDeathEvent {
+    pub player: Entity,
+}
+
 pub struct AnimatedSprite {
     pub texture: Handle<Image>,
     pub atlas_layout: Handle<TextureAtlasLayout>,
@@ -65,8 +73,9 @@ pub struct AnimatedSprite {
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<PlayerDeathEvent>();
         app.add_systems(OnEnter(AppState::InGame), setup);
-        app.add_systems(Update, (animate_sprite, update_sprite_facing, player_sprite_follow_mouse).run_if(in_state(AppState::InGame)));
+        app.add_systems(Update, (animate_sprite, update_sprite_facing, player_sprite_follow_mouse, track_player_velocity).run_if(in_state(AppState::InGame)));
         app.add_systems(Update, (update_system).run_if(in_state(AppState::InGame)));
     }
 }
@@ -177,13 +186,19 @@ fn setup(
         Name::new("Player"),
         LockedAxes::ROTATION_LOCKED,
         PlayerColliding(false),
+        Faction::Player,
+        OnGameScreen,
     )).id();
 
     commands.entity(player_entity).with_children(|parent| {
         parent.spawn((
             TransformBundle::from(Transform { translation: Vec3::new(0.0, -9.0, 0.0), ..Default::default()}),
             Collider::cuboid(8.0, 5.0),
-            KinematicCharacterController::default(),
+            Faction::Player.collision_groups(),
+            KinematicCharacterController {
+                filter_groups: Some(Faction::Player.movement_groups()),
+                ..Default::default()
+            },
             ActiveEvents::COLLISION_EVENTS,
         ));
     });
@@ -205,6 +220,25 @@ fn debug_player_velocity(velocity_query: Query<&Velocity, With<ControllablePlaye
     }
 }
 
+/// The player is moved by a character controller, so its velocity is measured from how far it actually moved.
+fn track_player_velocity(
+    time: Res<Time>,
+    mut player_query: Query<(&Transform, &mut Velocity), With<ControllablePlayer>>,
+    mut previous_position: Local<Option<Vec2>>,
+) {
+    let Ok((transform, mut velocity)) = player_query.get_single_mut() else {
+        *previous_position = None;
+        return;
+    };
+    let position = transform.translation.truncate();
+    if let Some(previous) = *previous_position {
+        if time.delta_seconds() > 0.0 {
+            velocity.0 = (position - previous) / time.delta_seconds();
+        }
+    }
+    *previous_position = Some(position);
+}
+
 fn update_system(mut controllers: Query<&mut KinematicCharacterController>) {
     for mut controller in controllers.iter_mut() {
         controller.translation = Some(Vec2::new(1.0, -0.5));
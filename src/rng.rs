@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::AppState;
+
+pub struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(GameRng::new(initial_seed()));
+        app.add_systems(Startup, log_seed);
+        app.add_systems(OnExit(AppState::InGame), reseed_for_next_run);
+    }
+}
+
+/// Independent random sequences, so e.g. an extra loot roll does not change what the AI does next.
+/// Systems that share a stream must be ordered against each other, otherwise the scheduler may
+/// let them draw in a different order every run. Most streams have a single consumer for that reason.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RngStream {
+    Spawning,
+    /// Drops from slain enemies.
+    Loot,
+    /// Critical hits and other damage rolls.
+    Combat,
+    Ai,
+    Dungeon,
+    /// Damage rolls of status effect ticks.
+    StatusEffects,
+    /// Chest contents.
+    Chests,
+}
+
+/// The one source of gameplay randomness. Every stream is derived from the run seed,
+/// so the same seed and the same inputs replay the same run.
+#[derive(Resource, Debug)]
+pub struct GameRng {
+    seed: u64,
+    streams: HashMap<RngStream, StdRng>,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        GameRng { seed, streams: HashMap::new() }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts every stream from a new seed.
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.streams.clear();
+    }
+
+    pub fn stream(&mut self, stream: RngStream) -> &mut StdRng {
+        let seed = self.seed;
+        self.streams.entry(stream).or_insert_with(|| {
+            // Spread the stream index over all bits so neighbouring streams do not start out correlated
+            let stream_seed = seed ^ (stream as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            StdRng::seed_from_u64(stream_seed)
+        })
+    }
+}
+
+/// Set GAME_SEED to replay a run, otherwise every run gets a fresh seed.
+fn initial_seed() -> u64 {
+    std::env::var("GAME_SEED").ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(rand::random)
+}
+
+fn log_seed(
+    game_rng: Res<GameRng>,
+) {
+    println!("Game seed {}", game_rng.seed());
+}
+
+fn reseed_for_next_run(
+    mut game_rng: ResMut<GameRng>,
+) {
+    let seed = initial_seed();
+    game_rng.reseed(seed);
+    println!("Game seed {}", seed);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    fn draw(game_rng: &mut GameRng, stream: RngStream) -> Vec<u64> {
+        (0..8).map(|_| game_rng.stream(stream).gen()).collect()
+    }
+
+    #[test]
+    fn same_seed_replays_every_stream() {
+        let mut first = GameRng::new(42);
+        let mut second = GameRng::new(42);
+        for stream in [RngStream::Spawning, RngStream::Loot, RngStream::Combat, RngStream::Ai, RngStream::Dungeon, RngStream::StatusEffects, RngStream::Chests] {
+            assert_eq!(draw(&mut first, stream), draw(&mut second, stream));
+        }
+    }
+
+    #[test]
+    fn streams_are_independent() {
+        let mut untouched = GameRng::new(42);
+        let mut busy = GameRng::new(42);
+        // Extra draws from one stream must not shift another
+        draw(&mut busy, RngStream::Loot);
+        assert_eq!(draw(&mut untouched, RngStream::Combat), draw(&mut busy, RngStream::Combat));
+        assert_ne!(draw(&mut untouched, RngStream::Loot), draw(&mut untouched, RngStream::Chests));
+    }
+
+    #[test]
+    fn reseed_restarts_streams() {
+        let mut game_rng = GameRng::new(1);
+        let expected = draw(&mut GameRng::new(2), RngStream::Ai);
+        draw(&mut game_rng, RngStream::Ai);
+        game_rng.reseed(2);
+        assert_eq!(draw(&mut game_rng, RngStream::Ai), expected);
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::Read;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    archetype::{spawn_enemy, EnemyArchetypes},
+    network::ClientLobby,
+    navigation::NavigationGrids,
+    player::{ControllablePlayer, PlayerDead},
+    rng::{GameRng, RngStream},
+    spritesheet::{SpriteCollection, TextureAtlases},
+    AppState, SCALE,
+};
+
+const ENCOUNTERS_PATH: &str = "./assets/data/encounters.json";
+/// Random positions tried before an enemy is spawned on the spawner itself.
+const SPAWN_POSITION_ATTEMPTS: u32 = 8;
+/// Extra enemies per additional connected player, as a fraction of the base count.
+const EXTRA_PLAYER_SCALING: f32 = 0.5;
+
+pub struct SpawnerPlugin;
+
+impl Plugin for SpawnerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Encounters::default());
+        app.insert_resource(EncounterDirector::default());
+        app.register_ldtk_entity::<SpawnerBundle>("Spawner");
+        app.add_systems(Startup, load_encounters);
+        app.add_systems(Update, (update_director, update_spawners).chain().run_if(in_state(AppState::InGame)));
+        app.add_systems(OnExit(AppState::InGame), reset_spawners);
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub enum SpawnCondition {
+    /// Starts when the player comes within `radius` of the spawner, in world units like `Encounter::spawn_radius`.
+    PlayerWithin { radius: f32 },
+    /// Starts when the player enters the rectangle the spawner covers in the level.
+    RoomEntered,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct WaveGroup {
+    pub archetype: String,
+    pub count: u32,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Wave {
+    pub enemies: Vec<WaveGroup>,
+    /// Seconds between individual spawns within the wave.
+    #[serde(default = "default_spawn_interval")]
+    pub spawn_interval: f32,
+}
+
+fn default_spawn_interval() -> f32 {
+    0.5
+}
+
+/// A sequence of waves as described in `assets/data/encounters.json`.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Encounter {
+    pub id: String,
+    pub condition: SpawnCondition,
+    pub waves: Vec<Wave>,
+    /// Delay after the condition is met before the first wave.
+    #[serde(default)]
+    pub start_delay: f32,
+    /// Pause between clearing a wave and starting the next.
+    #[serde(default)]
+    pub wave_cooldown: f32,
+    /// Most enemies from this spawner alive at once, before scaling by the director.
+    pub max_alive: u32,
+    /// In world units.
+    pub spawn_radius: f32,
+}
+
+#[derive(Resource, Default)]
+pub struct Encounters {
+    pub encounters: HashMap<String, Encounter>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn enemy_multiplier(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.75,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.5,
+        }
+    }
+}
+
+/// Scales every encounter by difficulty and by how many players are in the game.
+#[derive(Resource, Debug)]
+pub struct EncounterDirector {
+    pub difficulty: Difficulty,
+    pub player_count: usize,
+}
+
+impl Default for EncounterDirector {
+    fn default() -> Self {
+        EncounterDirector { difficulty: Difficulty::default(), player_count: 1 }
+    }
+}
+
+impl EncounterDirector {
+    pub fn multiplier(&self) -> f32 {
+        let extra_players = self.player_count.saturating_sub(1) as f32;
+        self.difficulty.enemy_multiplier() * (1.0 + extra_players * EXTRA_PLAYER_SCALING)
+    }
+
+    /// Scaled number of enemies, never less than one for a non-empty group.
+    pub fn scale(&self, count: u32) -> u32 {
+        if count == 0 {
+            return 0;
+        }
+        ((count as f32 * self.multiplier()).round() as u32).max(1)
+    }
+}
+
+#[derive(Debug)]
+enum SpawnerState {
+    Waiting,
+    /// Counting down to `wave`, either the start delay or the cooldown after the previous wave.
+    Starting { wave: usize, timer: Timer },
+    Spawning { wave: usize, queue: VecDeque<String>, timer: Timer },
+    /// Everything in `wave` has spawned, waiting for it to be cleared.
+    Clearing { wave: usize },
+    Finished,
+}
+
+/// Runs the encounter named in its "encounter" field, placed as "Spawner" in the LDtk level.
+/// The size of the LDtk entity is the room used by `SpawnCondition::RoomEntered`.
+#[derive(Component, Debug)]
+pub struct Spawner {
+    pub encounter: String,
+    /// Size of the room in level pixels.
+    pub size: Vec2,
+    state: SpawnerState,
+}
+
+impl Default for Spawner {
+    fn default() -> Self {
+        Spawner { encounter: String::new(), size: Vec2::ZERO, state: SpawnerState::Waiting }
+    }
+}
+
+impl From<&EntityInstance> for Spawner {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Spawner {
+            encounter: entity_instance.get_string_field("encounter").cloned().unwrap_or_default(),
+            size: Vec2::new(entity_instance.width as f32, entity_instance.height as f32),
+            state: SpawnerState::Waiting,
+        }
+    }
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct SpawnerBundle {
+    #[from_entity_instance]
+    spawner: Spawner,
+}
+
+/// Which spawner an enemy came from, so spawners can count what they still have alive.
+#[derive(Component)]
+pub struct SpawnedBy(pub Entity);
+
+fn load_encounters(
+    mut encounters: ResMut<Encounters>,
+) {
+    let mut file = File::open(ENCOUNTERS_PATH).expect("Failed to open encounters file");
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).expect("Failed to read encounters file");
+    let loaded: Vec<Encounter> = serde_json::from_str(&contents).expect("Failed to parse encounters");
+
+    for encounter in loaded {
+        println!("Loaded encounter {}", encounter.id);
+        encounters.encounters.insert(encounter.id.clone(), encounter);
+    }
+}
+
+fn update_director(
+    mut director: ResMut<EncounterDirector>,
+    lobby: Option<Res<ClientLobby>>,
+) {
+    // The lobby includes the local player once connected, offline play counts as one player
+    let player_count = lobby.map_or(1, |lobby| lobby.player_count().max(1));
+    if director.player_count != player_count {
+        println!("Encounter director scaling for {} players", player_count);
+        director.player_count = player_count;
+    }
+}
+
+fn reset_spawners(
+    mut spawner_query: Query<&mut Spawner>,
+) {
+    for mut spawner in &mut spawner_query {
+        spawner.state = SpawnerState::Waiting;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_spawners(
+    mut commands: Commands,
+    time: Res<Time>,
+    encounters: Res<Encounters>,
+    director: Res<EncounterDirector>,
+    navigation_grids: Res<NavigationGrids>,
+    enemy_archetypes: Res<EnemyArchetypes>,
+    texture_atlas: Res<TextureAtlases>,
+    sprite_collection: Res<SpriteCollection>,
+    mut spawner_query: Query<(Entity, &GlobalTransform, &mut Spawner)>,
+    spawned_query: Query<&SpawnedBy>,
+    player_query: Query<&Transform, (With<ControllablePlayer>, Without<PlayerDead>)>,
+    mut game_rng: ResMut<GameRng>,
+) {
+    let rng = game_rng.stream(RngStream::Spawning);
+
+    for (entity, global_transform, mut spawner) in &mut spawner_query {
+        let Some(encounter) = encounters.encounters.get(&spawner.encounter) else {
+            continue;
+        };
+        let position = global_transform.translation().truncate();
+        let alive = spawned_query.iter().filter(|spawned_by| spawned_by.0 == entity).count() as u32;
+
+        spawner.state = match std::mem::replace(&mut spawner.state, SpawnerState::Finished) {
+            SpawnerState::Waiting => {
+                let half_size = spawner.size * SCALE / 2.0;
+                let triggered = player_query.iter().any(|player| {
+                    let offset = player.translation.truncate() - position;
+                    match encounter.condition {
+                        SpawnCondition::PlayerWithin { radius } => offset.length() <= radius,
+                        SpawnCondition::RoomEntered => offset.x.abs() <= half_size.x && offset.y.abs() <= half_size.y,
+                    }
+                });
+                if triggered {
+                    println!("Encounter {} started", encounter.id);
+                    SpawnerState::Starting { wave: 0, timer: Timer::from_seconds(encounter.start_delay, TimerMode::Once) }
+                } else {
+                    SpawnerState::Waiting
+                }
+            },
+            SpawnerState::Starting { wave, mut timer } => {
+                timer.tick(time.delta());
+                if timer.finished() {
+                    let Some(next_wave) = encounter.waves.get(wave) else {
+                        continue;
+                    };
+                    println!("Encounter {} wave {}", encounter.id, wave + 1);
+                    let queue = next_wave.enemies.iter()
+                        .flat_map(|group| std::iter::repeat(group.archetype.clone()).take(director.scale(group.count) as usize))
+                        .collect();
+                    let mut timer = Timer::from_seconds(next_wave.spawn_interval, TimerMode::Repeating);
+                    // The first enemy of a wave appears on the next tick
+                    timer.set_elapsed(timer.duration());
+                    SpawnerState::Spawning { wave, queue, timer }
+                } else {
+                    SpawnerState::Starting { wave, timer }
+                }
+            },
+            SpawnerState::Spawning { wave, mut queue, mut timer } => {
+                timer.tick(time.delta());
+                if timer.just_finished() && alive < director.scale(encounter.max_alive) {
+                    if let Some(archetype) = queue.pop_front() {
+                        let spawn_position = find_spawn_position(&navigation_grids, position, encounter.spawn_radius, rng);
+                        if let Some(enemy) = spawn_enemy(&mut commands, &enemy_archetypes, &texture_atlas, &sprite_collection, &archetype, spawn_position) {
+                            commands.entity(enemy).insert(SpawnedBy(entity));
+                        }
+                    }
+                }
+                if queue.is_empty() {
+                    SpawnerState::Clearing { wave }
+                } else {
+                    SpawnerState::Spawning { wave, queue, timer }
+                }
+            },
+            SpawnerState::Clearing { wave } => {
+                if alive > 0 {
+                    SpawnerState::Clearing { wave }
+                } else if wave + 1 < encounter.waves.len() {
+                    SpawnerState::Starting { wave: wave + 1, timer: Timer::from_seconds(encounter.wave_cooldown, TimerMode::Once) }
+                } else {
+                    println!("Encounter {} cleared", encounter.id);
+                    SpawnerState::Finished
+                }
+            },
+            SpawnerState::Finished => SpawnerState::Finished,
+        };
+    }
+}
+
+/// A random walkable point within `radius` of the spawner that it can see, so enemies never appear
+/// inside or behind walls.
+fn find_spawn_position(navigation_grids: &NavigationGrids, origin: Vec2, radius: f32, rng: &mut impl Rng) -> Vec2 {
+    let Some(grid) = navigation_grids.grid_at(origin) else {
+        return origin;
+    };
+    for _ in 0..SPAWN_POSITION_ATTEMPTS {
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let distance = rng.gen_range(0.0..=radius);
+        let candidate = origin + Vec2::new(angle.cos(), angle.sin()) * distance;
+        if grid.is_walkable(grid.world_to_cell(candidate)) && grid.has_line_of_sight(origin, candidate) {
+            return candidate;
+        }
+    }
+    origin
+}
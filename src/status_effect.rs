@@ -0,0 +1,247 @@
+use bevy::prelude::*;
+
+use crate::{combat::{damage_player, Invulnerable}, damage::{calculate_damage, Armor, Damage, DamageType, Resistances}, enemy::{spawn_damage_number, EnemyAttack, EnemyHealth}, healthbar::PlayerHealth, player::{ControllablePlayer, PlayerDead, PlayerDeathEvent}, rng::{GameRng, RngStream}, AppState};
+
+pub struct StatusEffectPlugin;
+
+impl Plugin for StatusEffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (add_player_status_effects, tick_status_effects, update_status_tint).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StatusEffectKind {
+    Burn,
+    Freeze,
+    Slow,
+    Poison,
+}
+
+/// How a newly applied effect combines with one of the same kind that is already active.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackingRule {
+    /// Keep a single instance and restart its duration.
+    Refresh,
+    /// Add a stack (up to `max_stacks`) and restart the duration. Damage and slow scale with stacks.
+    Stack { max_stacks: u32 },
+    /// Ignore the new effect while one is active.
+    Ignore,
+}
+
+impl StatusEffectKind {
+    pub fn stacking_rule(&self) -> StackingRule {
+        match self {
+            StatusEffectKind::Burn => StackingRule::Refresh,
+            StatusEffectKind::Freeze => StackingRule::Ignore,
+            StatusEffectKind::Slow => StackingRule::Stack { max_stacks: 3 },
+            StatusEffectKind::Poison => StackingRule::Stack { max_stacks: 5 },
+        }
+    }
+
+    pub fn damage_type(&self) -> DamageType {
+        match self {
+            StatusEffectKind::Burn => DamageType::Fire,
+            StatusEffectKind::Freeze | StatusEffectKind::Slow => DamageType::Ice,
+            StatusEffectKind::Poison => DamageType::Poison,
+        }
+    }
+
+    fn tint(&self) -> Color {
+        match self {
+            StatusEffectKind::Burn => Color::rgb(1.0, 0.55, 0.35),
+            StatusEffectKind::Freeze => Color::rgb(0.55, 0.8, 1.0),
+            StatusEffectKind::Slow => Color::rgb(0.75, 0.85, 1.0),
+            StatusEffectKind::Poison => Color::rgb(0.6, 1.0, 0.5),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StatusEffect {
+    pub kind: StatusEffectKind,
+    pub duration: Timer,
+    pub tick: Option<Timer>,
+    pub damage_per_tick: f32,
+    pub stacks: u32,
+}
+
+impl StatusEffect {
+    pub fn burn() -> Self {
+        StatusEffect {
+            kind: StatusEffectKind::Burn,
+            duration: Timer::from_seconds(3.0, TimerMode::Once),
+            tick: Some(Timer::from_seconds(0.5, TimerMode::Repeating)),
+            damage_per_tick: 2.0,
+            stacks: 1,
+        }
+    }
+
+    pub fn poison() -> Self {
+        StatusEffect {
+            kind: StatusEffectKind::Poison,
+            duration: Timer::from_seconds(5.0, TimerMode::Once),
+            tick: Some(Timer::from_seconds(1.0, TimerMode::Repeating)),
+            damage_per_tick: 1.0,
+            stacks: 1,
+        }
+    }
+
+    /// Weaker and shorter than `poison`, the player only has a handful of half-hearts.
+    pub fn player_poison() -> Self {
+        StatusEffect {
+            kind: StatusEffectKind::Poison,
+            duration: Timer::from_seconds(3.0, TimerMode::Once),
+            tick: Some(Timer::from_seconds(1.0, TimerMode::Repeating)),
+            damage_per_tick: 0.5,
+            stacks: 1,
+        }
+    }
+
+    pub fn slow() -> Self {
+        StatusEffect {
+            kind: StatusEffectKind::Slow,
+            duration: Timer::from_seconds(2.5, TimerMode::Once),
+            tick: None,
+            damage_per_tick: 0.0,
+            stacks: 1,
+        }
+    }
+
+    pub fn freeze() -> Self {
+        StatusEffect {
+            kind: StatusEffectKind::Freeze,
+            duration: Timer::from_seconds(1.5, TimerMode::Once),
+            tick: None,
+            damage_per_tick: 0.0,
+            stacks: 1,
+        }
+    }
+}
+
+#[derive(Component, Default, Debug)]
+pub struct StatusEffects {
+    pub effects: Vec<StatusEffect>,
+    /// Damage over time that has not yet added up to a whole half-heart, only used for the player.
+    pending_damage: f32,
+}
+
+impl StatusEffects {
+    pub fn apply(&mut self, effect: StatusEffect) {
+        let Some(active) = self.effects.iter_mut().find(|active| active.kind == effect.kind) else {
+            self.effects.push(effect);
+            return;
+        };
+
+        match effect.kind.stacking_rule() {
+            StackingRule::Refresh => {
+                active.duration.reset();
+            },
+            StackingRule::Stack { max_stacks } => {
+                active.stacks = (active.stacks + effect.stacks).min(max_stacks);
+                active.duration.reset();
+            },
+            StackingRule::Ignore => {},
+        }
+    }
+
+    pub fn has(&self, kind: StatusEffectKind) -> bool {
+        self.effects.iter().any(|effect| effect.kind == kind)
+    }
+
+    pub fn stacks(&self, kind: StatusEffectKind) -> u32 {
+        self.effects.iter()
+            .find(|effect| effect.kind == kind)
+            .map_or(0, |effect| effect.stacks)
+    }
+
+    /// Multiplier applied to movement speed, 0.0 while frozen.
+    pub fn movement_multiplier(&self) -> f32 {
+        if self.has(StatusEffectKind::Freeze) {
+            return 0.0;
+        }
+        let slow_stacks = self.stacks(StatusEffectKind::Slow);
+        1.0 - 0.2 * slow_stacks as f32
+    }
+
+    fn tint(&self) -> Color {
+        self.effects.last().map_or(Color::WHITE, |effect| effect.kind.tint())
+    }
+}
+
+fn add_player_status_effects(
+    mut commands: Commands,
+    player_query: Query<Entity, (With<ControllablePlayer>, Without<StatusEffects>)>,
+) {
+    for player in &player_query {
+        commands.entity(player).insert(StatusEffects::default());
+    }
+}
+
+fn tick_status_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    mut game_rng: ResMut<GameRng>,
+    mut player_health: ResMut<PlayerHealth>,
+    mut death_events: EventWriter<PlayerDeathEvent>,
+    mut query: Query<(Entity, &Transform, &mut StatusEffects, Option<&mut EnemyHealth>, Option<&Resistances>, Option<&Armor>, Has<ControllablePlayer>, Has<PlayerDead>, Has<Invulnerable>)>,
+) {
+    let rng = game_rng.stream(RngStream::StatusEffects);
+    for (entity, transform, mut status_effects, mut health, resistances, armor, is_player, is_dead, is_invulnerable) in &mut query {
+        // Effects end with the player, so they do not carry over into the respawn
+        if is_dead {
+            if !status_effects.effects.is_empty() {
+                status_effects.effects.clear();
+                status_effects.pending_damage = 0.0;
+            }
+            continue;
+        }
+        let mut player_damage = 0.0;
+        for effect in status_effects.effects.iter_mut() {
+            effect.duration.tick(time.delta());
+
+            let Some(tick) = effect.tick.as_mut() else {
+                continue;
+            };
+            tick.tick(time.delta());
+            if !tick.just_finished() {
+                continue;
+            }
+
+            let damage = Damage::new(effect.damage_per_tick * effect.stacks as f32, effect.kind.damage_type());
+            let result = calculate_damage(&damage, resistances, armor, rng);
+            if let Some(health) = health.as_mut() {
+                health.current -= result.amount;
+            }
+            // Ticks landing during invulnerability frames are lost, like hits
+            if is_player && !is_invulnerable {
+                player_damage += result.amount;
+            }
+            if health.is_some() || (is_player && !is_invulnerable) {
+                spawn_damage_number(&mut commands, &asset_server, &result, transform.translation.truncate().extend(7.0));
+            }
+        }
+        status_effects.effects.retain(|effect| !effect.duration.finished());
+
+        // Player health is whole half-hearts, so fractional ticks add up until they cost one
+        status_effects.pending_damage += player_damage;
+        let lost_health = status_effects.pending_damage.floor();
+        if lost_health >= 1.0 {
+            status_effects.pending_damage -= lost_health;
+            damage_player(&mut commands, &mut player_health, &mut death_events, entity, lost_health);
+        }
+    }
+}
+
+fn update_status_tint(
+    mut query: Query<(&StatusEffects, &mut Sprite, Option<&EnemyAttack>), (Changed<StatusEffects>, Without<PlayerDead>)>,
+) {
+    for (status_effects, mut sprite, attack) in &mut query {
+        // Attack telegraphs take priority over status tints
+        if attack.is_some_and(|attack| attack.winding_up) {
+            continue;
+        }
+        sprite.color = status_effects.tint();
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{collision::WALL_GROUP, enemy::Enemy, AppState};
+
+/// Size of a spatial hash cell, at least the largest neighbour radius so queries only touch 3x3 cells.
+const SPATIAL_HASH_CELL_SIZE: f32 = 80.0;
+/// Angle between the middle obstacle feeler and the two side feelers.
+const FEELER_ANGLE: f32 = 0.5;
+
+pub struct SteeringPlugin;
+
+impl Plugin for SteeringPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SpatialHash::new(SPATIAL_HASH_CELL_SIZE));
+        app.add_systems(PreUpdate, update_spatial_hash.run_if(in_state(AppState::InGame)));
+    }
+}
+
+/// Enemy positions bucketed into a uniform grid so neighbour lookups stay cheap with hundreds of enemies.
+#[derive(Resource)]
+pub struct SpatialHash {
+    cell_size: f32,
+    cells: HashMap<IVec2, Vec<(Entity, Vec2)>>,
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialHash { cell_size, cells: HashMap::new() }
+    }
+
+    fn cell(&self, position: Vec2) -> IVec2 {
+        (position / self.cell_size).floor().as_ivec2()
+    }
+
+    pub fn clear(&mut self) {
+        // Keep the buckets that were used last frame, the same cells are usually occupied again.
+        // Buckets that stayed empty are dropped so the map does not grow with every cell ever visited.
+        self.cells.retain(|_, bucket| {
+            let keep = !bucket.is_empty();
+            bucket.clear();
+            keep
+        });
+    }
+
+    pub fn insert(&mut self, entity: Entity, position: Vec2) {
+        let cell = self.cell(position);
+        self.cells.entry(cell).or_default().push((entity, position));
+    }
+
+    /// Everything within `radius` of `position`, including the entity at `position` itself if it was inserted.
+    pub fn neighbours(&self, position: Vec2, radius: f32) -> impl Iterator<Item = (Entity, Vec2)> + '_ {
+        let min = self.cell(position - Vec2::splat(radius));
+        let max = self.cell(position + Vec2::splat(radius));
+        (min.x..=max.x)
+            .flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .filter(move |(_, other)| other.distance_squared(position) <= radius * radius)
+    }
+}
+
+fn update_spatial_hash(
+    mut spatial_hash: ResMut<SpatialHash>,
+    enemy_query: Query<(Entity, &Transform), With<Enemy>>,
+) {
+    spatial_hash.clear();
+    for (entity, transform) in &enemy_query {
+        spatial_hash.insert(entity, transform.translation.truncate());
+    }
+}
+
+/// Push away from neighbours closer than `radius`, stronger the closer they are.
+/// The result has a length of roughly the number of crowding neighbours.
+pub fn separation(spatial_hash: &SpatialHash, entity: Entity, position: Vec2, radius: f32) -> Vec2 {
+    let mut force = Vec2::ZERO;
+    for (other, other_position) in spatial_hash.neighbours(position, radius) {
+        if other == entity {
+            continue;
+        }
+        let offset = position - other_position;
+        let distance = offset.length();
+        // Enemies spawned on the same spot split in a direction picked from their id
+        let away = if distance > f32::EPSILON {
+            offset / distance
+        } else {
+            Vec2::from_angle(entity.index() as f32)
+        };
+        force += away * (1.0 - distance / radius);
+    }
+    force
+}
+
+/// Fraction of full speed to move at so the enemy slows down smoothly instead of overshooting its goal.
+pub fn arrival(distance_to_goal: f32, slowing_radius: f32) -> f32 {
+    (distance_to_goal / slowing_radius).clamp(0.0, 1.0)
+}
+
+/// Steer away from walls in front of the enemy, using three ray feelers along `direction`.
+pub fn avoid_obstacles(rapier_context: &RapierContext, position: Vec2, direction: Vec2, look_ahead: f32) -> Vec2 {
+    let filter = QueryFilter::new().groups(CollisionGroups::new(Group::ALL, WALL_GROUP));
+    let mut force = Vec2::ZERO;
+    for angle in [0.0, FEELER_ANGLE, -FEELER_ANGLE] {
+        let feeler = Vec2::from_angle(angle).rotate(direction);
+        if let Some((_, hit)) = rapier_context.cast_ray_and_get_normal(position, feeler, look_ahead, true, filter) {
+            force += hit.normal * (1.0 - hit.toi / look_ahead);
+        }
+    }
+    force
+}
@@ -0,0 +1,269 @@
+use bevy::prelude::*;
+use bevy::transform::TransformSystem;
+use bevy_ecs_ldtk::prelude::*;
+
+use crate::{
+    combat::Invulnerable,
+    dungeon::{DungeonFloor, DungeonRun, DUNGEON_LEVEL},
+    game::OnGameScreen,
+    level::RespawnPoint,
+    player::{ControllablePlayer, PlayerDead},
+    AppState,
+};
+
+const EXIT_SPRITE_PATH: &str = "./textures/rpg/floor_stairs.png";
+const FADE_SECONDS: f32 = 0.4;
+/// How close the player has to get to an exit to use it.
+const EXIT_RADIUS: f32 = 40.0;
+/// Fade back in anyway if the target level has no matching entrance.
+const ENTRANCE_TIMEOUT_SECONDS: f32 = 3.0;
+
+pub struct LevelTransitionPlugin;
+
+impl Plugin for LevelTransitionPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(LevelTransition::default());
+        app.register_ldtk_entity::<LevelExitBundle>("Exit");
+        app.register_ldtk_entity::<LevelEntranceBundle>("Entrance");
+        app.add_systems(OnEnter(AppState::InGame), setup_fade_overlay);
+        app.add_systems(OnExit(AppState::InGame), reset_level_transition);
+        app.add_systems(Update, (
+            setup_level_exits,
+            use_level_exits,
+            update_level_transition,
+            update_fade_overlay,
+        ).chain().run_if(in_state(AppState::InGame)));
+        // Entrances of a level that just loaded only have their world position after propagation
+        app.add_systems(PostUpdate, place_player_at_entrance.after(TransformSystem::TransformPropagate).run_if(in_state(AppState::InGame)));
+    }
+}
+
+/// Takes the player to the "Entrance" named `target_entrance` in `target_level`, placed as "Exit" in the LDtk level.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct LevelExit {
+    pub target_level: String,
+    pub target_entrance: String,
+}
+
+impl From<&EntityInstance> for LevelExit {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        LevelExit {
+            target_level: entity_instance.get_string_field("target_level").cloned().unwrap_or_default(),
+            target_entrance: entity_instance.get_string_field("target_entrance").cloned().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct LevelExitBundle {
+    #[from_entity_instance]
+    level_exit: LevelExit,
+}
+
+/// Where the player arrives through an exit, placed as "Entrance" in the LDtk level.
+/// The "id" field has to be unique across the whole world.
+#[derive(Clone, Eq, PartialEq, Debug, Default, Component)]
+pub struct LevelEntrance {
+    pub id: String,
+}
+
+impl From<&EntityInstance> for LevelEntrance {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        LevelEntrance {
+            id: entity_instance.get_string_field("id").cloned().unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone, Default, Bundle, LdtkEntity)]
+pub struct LevelEntranceBundle {
+    #[from_entity_instance]
+    level_entrance: LevelEntrance,
+}
+
+#[derive(Debug, Default)]
+enum TransitionState {
+    #[default]
+    Idle,
+    FadingOut { level: String, entrance: String },
+    /// The screen is black and the level is selected, waiting for the entrance to spawn.
+    Loading { level: String, entrance: String, timer: Timer },
+    FadingIn,
+}
+
+/// Moves the player between levels behind a fade to black.
+/// The player entity and its health, mana and inventory are untouched, only its position changes.
+#[derive(Resource, Debug, Default)]
+pub struct LevelTransition {
+    state: TransitionState,
+    /// Opacity of the fade overlay, from 0 to 1.
+    fade: f32,
+    /// Exits only work once the player has stepped off every exit, so arriving next to one
+    /// does not immediately send them back.
+    exits_armed: bool,
+}
+
+impl LevelTransition {
+    pub fn is_active(&self) -> bool {
+        !matches!(self.state, TransitionState::Idle)
+    }
+}
+
+#[derive(Component)]
+struct FadeOverlay;
+
+fn setup_fade_overlay(
+    mut commands: Commands,
+) {
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..Default::default()
+            },
+            background_color: Color::rgba(0.0, 0.0, 0.0, 0.0).into(),
+            z_index: ZIndex::Global(12),
+            ..Default::default()
+        },
+        FadeOverlay,
+        OnGameScreen,
+    ));
+}
+
+fn reset_level_transition(
+    mut level_transition: ResMut<LevelTransition>,
+) {
+    *level_transition = LevelTransition::default();
+}
+
+fn setup_level_exits(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    exit_query: Query<Entity, Added<LevelExit>>,
+) {
+    for entity in &exit_query {
+        // Sized in level pixels, the level transform scales it up like the walls
+        commands.entity(entity).insert((
+            Sprite {
+                custom_size: Some(Vec2::new(16.0, 16.0)),
+                ..Default::default()
+            },
+            asset_server.load::<Image>(EXIT_SPRITE_PATH),
+        ));
+    }
+}
+
+fn use_level_exits(
+    mut commands: Commands,
+    mut level_transition: ResMut<LevelTransition>,
+    exit_query: Query<(&GlobalTransform, &LevelExit)>,
+    player_query: Query<(Entity, &Transform), (With<ControllablePlayer>, Without<PlayerDead>)>,
+) {
+    if level_transition.is_active() {
+        return;
+    }
+    let Ok((player, player_transform)) = player_query.get_single() else {
+        return;
+    };
+    let player_position = player_transform.translation.truncate();
+    let exit = exit_query.iter()
+        .find(|(global_transform, _)| global_transform.translation().truncate().distance(player_position) <= EXIT_RADIUS)
+        .map(|(_, exit)| exit);
+
+    let Some(exit) = exit else {
+        level_transition.exits_armed = true;
+        return;
+    };
+    if !level_transition.exits_armed {
+        return;
+    }
+
+    println!("Leaving for {} through {}", exit.target_level, exit.target_entrance);
+    level_transition.exits_armed = false;
+    level_transition.state = TransitionState::FadingOut {
+        level: exit.target_level.clone(),
+        entrance: exit.target_entrance.clone(),
+    };
+    // Nothing can hurt the player while they cannot see
+    commands.entity(player).insert(Invulnerable::new(FADE_SECONDS * 2.0));
+}
+
+pub fn update_level_transition(
+    time: Res<Time>,
+    mut level_transition: ResMut<LevelTransition>,
+    mut level_selection: ResMut<LevelSelection>,
+) {
+    let fade_step = time.delta_seconds() / FADE_SECONDS;
+    let level_transition = &mut *level_transition;
+
+    level_transition.state = match std::mem::take(&mut level_transition.state) {
+        TransitionState::Idle => TransitionState::Idle,
+        TransitionState::FadingOut { level, entrance } => {
+            level_transition.fade = (level_transition.fade + fade_step).min(1.0);
+            if level_transition.fade >= 1.0 {
+                *level_selection = LevelSelection::Identifier(level.clone());
+                TransitionState::Loading { level, entrance, timer: Timer::from_seconds(ENTRANCE_TIMEOUT_SECONDS, TimerMode::Once) }
+            } else {
+                TransitionState::FadingOut { level, entrance }
+            }
+        },
+        TransitionState::Loading { level, entrance, mut timer } => {
+            timer.tick(time.delta());
+            if timer.finished() {
+                println!("No entrance {} found", entrance);
+                TransitionState::FadingIn
+            } else {
+                TransitionState::Loading { level, entrance, timer }
+            }
+        },
+        TransitionState::FadingIn => {
+            level_transition.fade = (level_transition.fade - fade_step).max(0.0);
+            if level_transition.fade <= 0.0 {
+                TransitionState::Idle
+            } else {
+                TransitionState::FadingIn
+            }
+        },
+    };
+}
+
+fn place_player_at_entrance(
+    mut level_transition: ResMut<LevelTransition>,
+    mut respawn_point: ResMut<RespawnPoint>,
+    dungeon_run: Res<DungeonRun>,
+    entrance_query: Query<(&GlobalTransform, &LevelEntrance, Option<&DungeonFloor>)>,
+    mut player_query: Query<&mut Transform, With<ControllablePlayer>>,
+) {
+    let TransitionState::Loading { level, entrance, .. } = &level_transition.state else {
+        return;
+    };
+    // A dungeon entrance only counts once the floor that is being generated has spawned
+    let floor = (level == DUNGEON_LEVEL).then_some(dungeon_run.floor);
+    let Some((global_transform, _, _)) = entrance_query.iter().find(|(_, level_entrance, entrance_floor)| {
+        &level_entrance.id == entrance && entrance_floor.map(|entrance_floor| entrance_floor.0) == floor
+    }) else {
+        return;
+    };
+    let position = global_transform.translation().truncate();
+
+    for mut player_transform in &mut player_query {
+        player_transform.translation.x = position.x;
+        player_transform.translation.y = position.y;
+    }
+    respawn_point.0 = Some(position);
+    level_transition.state = TransitionState::FadingIn;
+}
+
+fn update_fade_overlay(
+    level_transition: Res<LevelTransition>,
+    mut overlay_query: Query<&mut BackgroundColor, With<FadeOverlay>>,
+) {
+    if !level_transition.is_changed() {
+        return;
+    }
+    for mut background_color in &mut overlay_query {
+        background_color.0.set_a(level_transition.fade);
+    }
+}
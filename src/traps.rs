@@ -0,0 +1,570 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use bevy_ecs_ldtk::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    collision::{Faction, FactionCollisionEvent},
+    combat::{HitEvent, Invulnerable},
+    damage::{Damage, DamageType},
+    enemy::Enemy,
+    game::OnGameScreen,
+    interaction::{InteractAction, InteractEvent, Interactable},
+    inventory::Inventory,
+    level::key_field,
+    player::{ControllablePlayer, PlayerDead},
+    status_effect::{StatusEffect, StatusEffects},
+    AppState, SCALE,
+};
+
+const SPIKE_SPRITE_PATHS: [&str; 4] = [
+    "./sprites/items and trap_animation/peaks/peaks_1.png",
+    "./sprites/items and trap_animation/peaks/peaks_2.png",
+    "./sprites/items and trap_animation/peaks/peaks_3.png",
+    "./sprites/items and trap_animation/peaks/peaks_4.png",
+];
+const ARROW_SPRITE_PATH: &str = "./sprites/items and trap_animation/arrow/Just_arrow.png";
+const ARROW_TRAP_SPRITE_PATH: &str = "./sprites/items and trap_animation/arrow/arrow_1.png";
+const PRESSURE_PLATE_UP_SPRITE_PATH: &str = "./textures/rpg/button_red_up.png";
+const PRESSURE_PLATE_DOWN_SPRITE_PATH: &str = "./textures/rpg/button_red_down.png";
+const LEVER_UP_SPRITE_PATH: &str = "./textures/rpg/lever_left.png";
+const LEVER_PULLED_SPRITE_PATH: &str = "./textures/rpg/lever_right.png";
+/// Spikes show their half-raised frame this long before coming up, so there is time to step off.
+const SPIKE_WARNING_SECONDS: f32 = 0.4;
+const ARROW_SPEED: f32 = 500.0;
+/// Arrows spawn this far in front of the shooter so they clear its tile.
+const ARROW_SPAWN_OFFSET: f32 = 40.0;
+const ARROW_LIFETIME_SECONDS: f32 = 3.0;
+const TRAP_KNOCKBACK: f32 = 250.0;
+const TRAP_STUN_SECONDS: f32 = 0.2;
+
+pub struct TrapPlugin;
+
+impl Plugin for TrapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TrapSprites::default());
+        app.add_event::<TrapTriggerEvent>();
+        app.register_ldtk_entity::<SpikeTrapBundle>("SpikeTrap");
+        app.register_ldtk_entity::<PressurePlateBundle>("PressurePlate");
+        app.register_ldtk_entity::<LeverBundle>("Lever");
+        app.register_ldtk_entity::<ArrowTrapBundle>("ArrowTrap");
+        app.register_ldtk_entity::<DamageVolumeBundle>("DamageVolume");
+        app.add_systems(Startup, load_trap_sprites);
+        app.add_systems(Update, (
+            setup_trap_sprites,
+            update_pressure_plates,
+            pull_levers,
+            update_spike_traps,
+            update_arrow_traps,
+            update_damage_volumes,
+            arrow_collision,
+            despawn_expired_arrows,
+        ).chain().run_if(in_state(AppState::InGame)));
+    }
+}
+
+#[derive(Resource, Default)]
+struct TrapSprites {
+    spikes: Vec<Handle<Image>>,
+    arrow: Handle<Image>,
+    arrow_trap: Handle<Image>,
+    plate_up: Handle<Image>,
+    plate_down: Handle<Image>,
+    lever_up: Handle<Image>,
+    lever_pulled: Handle<Image>,
+}
+
+/// Sent when a pressure plate is stepped on or a lever is pulled, firing every trap whose "trigger" field matches `id`.
+#[derive(Event, Debug, Clone)]
+pub struct TrapTriggerEvent {
+    pub id: String,
+}
+
+fn trigger_field(entity_instance: &EntityInstance) -> Option<String> {
+    entity_instance.get_string_field("trigger").ok()
+        .filter(|trigger| !trigger.is_empty())
+        .cloned()
+}
+
+fn float_field(entity_instance: &EntityInstance, name: &str, default: f32) -> f32 {
+    entity_instance.get_float_field(name).copied().unwrap_or(default)
+}
+
+fn entity_size(entity_instance: &EntityInstance) -> Vec2 {
+    Vec2::new(entity_instance.width as f32, entity_instance.height as f32)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SpikeState {
+    #[default]
+    Down,
+    Warning,
+    Up,
+}
+
+/// Spikes that cycle up and down on a timer, or rise when their "trigger" plate is pressed.
+/// Everything standing on them when they are up is hit once per cycle.
+#[derive(Component, Debug, Default)]
+pub struct SpikeTrap {
+    pub damage: f32,
+    pub up_seconds: f32,
+    pub down_seconds: f32,
+    pub trigger: Option<String>,
+    /// Size in level pixels.
+    pub size: Vec2,
+    state: SpikeState,
+    timer: Timer,
+    hit: Vec<Entity>,
+}
+
+impl From<&EntityInstance> for SpikeTrap {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let down_seconds = float_field(entity_instance, "down_seconds", 2.0);
+        SpikeTrap {
+            damage: float_field(entity_instance, "damage", 1.0),
+            up_seconds: float_field(entity_instance, "up_seconds", 1.0),
+            down_seconds,
+            trigger: trigger_field(entity_instance),
+            size: entity_size(entity_instance),
+            state: SpikeState::Down,
+            timer: Timer::from_seconds((down_seconds - SPIKE_WARNING_SECONDS).max(0.0), TimerMode::Once),
+            hit: Vec::new(),
+        }
+    }
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct SpikeTrapBundle {
+    #[from_entity_instance]
+    spike_trap: SpikeTrap,
+}
+
+/// Pressed while a player or enemy stands on it.
+#[derive(Component, Debug, Default)]
+pub struct PressurePlate {
+    pub id: String,
+    pub size: Vec2,
+    pressed: bool,
+}
+
+impl From<&EntityInstance> for PressurePlate {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        PressurePlate {
+            id: entity_instance.get_string_field("id").cloned().unwrap_or_default(),
+            size: entity_size(entity_instance),
+            pressed: false,
+        }
+    }
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct PressurePlateBundle {
+    #[from_entity_instance]
+    pressure_plate: PressurePlate,
+}
+
+/// Fires the traps triggered by its "id" every time a player flips it, in either direction.
+/// Levers with a "key" field are locked until the first pull, which uses up that key.
+#[derive(Component, Debug, Default)]
+pub struct Lever {
+    pub id: String,
+    pub key: Option<String>,
+    pulled: bool,
+}
+
+impl From<&EntityInstance> for Lever {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        Lever {
+            id: entity_instance.get_string_field("id").cloned().unwrap_or_default(),
+            key: key_field(entity_instance),
+            pulled: false,
+        }
+    }
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct LeverBundle {
+    #[from_entity_instance]
+    lever: Lever,
+}
+
+/// Shoots arrows in its "direction" every `interval` seconds, or when its "trigger" plate is pressed.
+#[derive(Component, Debug, Default)]
+pub struct ArrowTrap {
+    pub direction: Vec2,
+    pub damage: f32,
+    pub trigger: Option<String>,
+    timer: Timer,
+}
+
+impl From<&EntityInstance> for ArrowTrap {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let direction = match entity_instance.get_string_field("direction").map(String::as_str) {
+            Ok("Left") => Vec2::NEG_X,
+            Ok("Up") => Vec2::Y,
+            Ok("Down") => Vec2::NEG_Y,
+            _ => Vec2::X,
+        };
+        ArrowTrap {
+            direction,
+            damage: float_field(entity_instance, "damage", 1.0),
+            trigger: trigger_field(entity_instance),
+            timer: Timer::from_seconds(float_field(entity_instance, "interval", 2.0), TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct ArrowTrapBundle {
+    #[from_entity_instance]
+    arrow_trap: ArrowTrap,
+}
+
+/// An area such as a fire pit or poison cloud that hurts everything inside it every `tick_seconds`.
+#[derive(Component, Debug, Default)]
+pub struct DamageVolume {
+    pub damage: f32,
+    pub damage_type: DamageType,
+    pub size: Vec2,
+    timer: Timer,
+}
+
+impl From<&EntityInstance> for DamageVolume {
+    fn from(entity_instance: &EntityInstance) -> Self {
+        let damage_type = match entity_instance.get_string_field("damage_type").map(String::as_str) {
+            Ok("Fire") => DamageType::Fire,
+            Ok("Ice") => DamageType::Ice,
+            Ok("Poison") => DamageType::Poison,
+            _ => DamageType::Physical,
+        };
+        DamageVolume {
+            damage: float_field(entity_instance, "damage", 1.0),
+            damage_type,
+            size: entity_size(entity_instance),
+            timer: Timer::from_seconds(float_field(entity_instance, "tick_seconds", 1.0), TimerMode::Repeating),
+        }
+    }
+}
+
+#[derive(Bundle, Default, LdtkEntity)]
+pub struct DamageVolumeBundle {
+    #[from_entity_instance]
+    damage_volume: DamageVolume,
+}
+
+#[derive(Component)]
+pub struct TrapArrow {
+    pub damage: f32,
+    lifetime: Timer,
+}
+
+fn load_trap_sprites(
+    asset_server: Res<AssetServer>,
+    mut trap_sprites: ResMut<TrapSprites>,
+) {
+    trap_sprites.spikes = SPIKE_SPRITE_PATHS.iter().map(|path| asset_server.load(*path)).collect();
+    trap_sprites.arrow = asset_server.load(ARROW_SPRITE_PATH);
+    trap_sprites.arrow_trap = asset_server.load(ARROW_TRAP_SPRITE_PATH);
+    trap_sprites.plate_up = asset_server.load(PRESSURE_PLATE_UP_SPRITE_PATH);
+    trap_sprites.plate_down = asset_server.load(PRESSURE_PLATE_DOWN_SPRITE_PATH);
+    trap_sprites.lever_up = asset_server.load(LEVER_UP_SPRITE_PATH);
+    trap_sprites.lever_pulled = asset_server.load(LEVER_PULLED_SPRITE_PATH);
+}
+
+/// Rotation for the trap sprites, which are drawn pointing down.
+fn facing_rotation(direction: Vec2) -> Quat {
+    Quat::from_rotation_z(direction.y.atan2(direction.x) + FRAC_PI_2)
+}
+
+#[allow(clippy::type_complexity)]
+fn setup_trap_sprites(
+    mut commands: Commands,
+    trap_sprites: Res<TrapSprites>,
+    spike_query: Query<(Entity, &SpikeTrap), Added<SpikeTrap>>,
+    plate_query: Query<(Entity, &PressurePlate), Added<PressurePlate>>,
+    lever_query: Query<(Entity, &Lever), Added<Lever>>,
+    mut arrow_trap_query: Query<(Entity, &ArrowTrap, &mut Transform), Added<ArrowTrap>>,
+    volume_query: Query<(Entity, &DamageVolume), Added<DamageVolume>>,
+) {
+    // Sized in level pixels, the level transform scales them up like the walls
+    for (entity, spike_trap) in &spike_query {
+        commands.entity(entity).insert((
+            Sprite { custom_size: Some(spike_trap.size), ..Default::default() },
+            trap_sprites.spikes[0].clone(),
+        ));
+    }
+    for (entity, plate) in &plate_query {
+        commands.entity(entity).insert((
+            Sprite { custom_size: Some(plate.size), ..Default::default() },
+            trap_sprites.plate_up.clone(),
+        ));
+    }
+    for (entity, lever) in &lever_query {
+        commands.entity(entity).insert((
+            Sprite { custom_size: Some(Vec2::new(16.0, 16.0)), ..Default::default() },
+            trap_sprites.lever_up.clone(),
+            Interactable::new(InteractAction::PullLever, if lever.key.is_some() { "Unlock" } else { "Pull" }),
+        ));
+    }
+    for (entity, arrow_trap, mut transform) in &mut arrow_trap_query {
+        transform.rotation = facing_rotation(arrow_trap.direction);
+        commands.entity(entity).insert((
+            Sprite { custom_size: Some(Vec2::new(16.0, 32.0)), ..Default::default() },
+            trap_sprites.arrow_trap.clone(),
+        ));
+    }
+    for (entity, volume) in &volume_query {
+        let color = volume.damage_type.color().with_a(0.35);
+        commands.entity(entity).insert((
+            Sprite { color, custom_size: Some(volume.size), ..Default::default() },
+            Handle::<Image>::default(),
+        ));
+    }
+}
+
+/// Players and enemies, everything traps can hurt.
+type TrapTargetQuery<'w, 's> = Query<'w, 's, (Entity, &'static Transform), (Or<(With<ControllablePlayer>, With<Enemy>)>, Without<PlayerDead>)>;
+
+/// Targets whose position lies inside the rectangle of `size` level pixels centred on `centre`.
+fn occupants(centre: Vec2, size: Vec2, target_query: &TrapTargetQuery) -> Vec<Entity> {
+    let half_size = size * SCALE / 2.0;
+    target_query.iter()
+        .filter(|(_, transform)| {
+            let offset = (transform.translation.truncate() - centre).abs();
+            offset.x <= half_size.x && offset.y <= half_size.y
+        })
+        .map(|(entity, _)| entity)
+        .collect()
+}
+
+fn trap_hit(target: Entity, source: Vec2, damage: Damage) -> HitEvent {
+    HitEvent {
+        target,
+        source_position: source.extend(0.0),
+        damage,
+        knockback: TRAP_KNOCKBACK,
+        stun: TRAP_STUN_SECONDS,
+    }
+}
+
+fn update_pressure_plates(
+    trap_sprites: Res<TrapSprites>,
+    mut plate_query: Query<(&GlobalTransform, &mut PressurePlate, &mut Handle<Image>)>,
+    target_query: TrapTargetQuery,
+    mut trigger_events: EventWriter<TrapTriggerEvent>,
+) {
+    for (global_transform, mut plate, mut texture) in &mut plate_query {
+        let pressed = !occupants(global_transform.translation().truncate(), plate.size, &target_query).is_empty();
+        if pressed == plate.pressed {
+            continue;
+        }
+        plate.pressed = pressed;
+        *texture = if pressed { trap_sprites.plate_down.clone() } else { trap_sprites.plate_up.clone() };
+        if pressed {
+            println!("Pressure plate {} pressed", plate.id);
+            trigger_events.send(TrapTriggerEvent { id: plate.id.clone() });
+        }
+    }
+}
+
+fn pull_levers(
+    trap_sprites: Res<TrapSprites>,
+    mut inventory: ResMut<Inventory>,
+    mut interact_events: EventReader<InteractEvent>,
+    mut lever_query: Query<(&mut Lever, &mut Interactable, &mut Handle<Image>)>,
+    mut trigger_events: EventWriter<TrapTriggerEvent>,
+) {
+    for event in interact_events.read() {
+        let Ok((mut lever, mut interactable, mut texture)) = lever_query.get_mut(event.target) else {
+            continue;
+        };
+        if !inventory.use_key(lever.key.as_deref()) {
+            println!("Lever {} is locked, it needs {:?}", lever.id, lever.key);
+            continue;
+        }
+        if lever.key.take().is_some() {
+            interactable.prompt = "Pull".to_string();
+        }
+
+        lever.pulled = !lever.pulled;
+        *texture = if lever.pulled { trap_sprites.lever_pulled.clone() } else { trap_sprites.lever_up.clone() };
+        println!("Lever {} pulled", lever.id);
+        trigger_events.send(TrapTriggerEvent { id: lever.id.clone() });
+    }
+}
+
+fn is_triggered(trigger: &Option<String>, triggered_ids: &[String]) -> bool {
+    trigger.as_ref().is_some_and(|trigger| triggered_ids.contains(trigger))
+}
+
+fn update_spike_traps(
+    time: Res<Time>,
+    trap_sprites: Res<TrapSprites>,
+    mut trigger_events: EventReader<TrapTriggerEvent>,
+    mut spike_query: Query<(&GlobalTransform, &mut SpikeTrap, &mut Handle<Image>)>,
+    target_query: TrapTargetQuery,
+    mut hit_events: EventWriter<HitEvent>,
+) {
+    let triggered_ids: Vec<String> = trigger_events.read().map(|event| event.id.clone()).collect();
+
+    for (global_transform, mut spike_trap, mut texture) in &mut spike_query {
+        let spike_trap = spike_trap.as_mut();
+        let position = global_transform.translation().truncate();
+
+        if spike_trap.state == SpikeState::Down && spike_trap.trigger.is_some() {
+            // Triggered spikes wait for their plate instead of cycling
+            if is_triggered(&spike_trap.trigger, &triggered_ids) {
+                spike_trap.state = SpikeState::Warning;
+                spike_trap.timer = Timer::from_seconds(SPIKE_WARNING_SECONDS, TimerMode::Once);
+                *texture = trap_sprites.spikes[1].clone();
+            }
+            continue;
+        }
+
+        spike_trap.timer.tick(time.delta());
+        if spike_trap.timer.finished() {
+            let (state, seconds, frame) = match spike_trap.state {
+                SpikeState::Down => (SpikeState::Warning, SPIKE_WARNING_SECONDS, 1),
+                SpikeState::Warning => (SpikeState::Up, spike_trap.up_seconds, 3),
+                SpikeState::Up => (SpikeState::Down, (spike_trap.down_seconds - SPIKE_WARNING_SECONDS).max(0.0), 0),
+            };
+            spike_trap.state = state;
+            spike_trap.timer = Timer::from_seconds(seconds, TimerMode::Once);
+            spike_trap.hit.clear();
+            *texture = trap_sprites.spikes[frame].clone();
+        }
+
+        if spike_trap.state != SpikeState::Up {
+            continue;
+        }
+        for target in occupants(position, spike_trap.size, &target_query) {
+            if spike_trap.hit.contains(&target) {
+                continue;
+            }
+            spike_trap.hit.push(target);
+            hit_events.send(trap_hit(target, position, Damage::new(spike_trap.damage, DamageType::Physical)));
+        }
+    }
+}
+
+fn update_arrow_traps(
+    mut commands: Commands,
+    time: Res<Time>,
+    trap_sprites: Res<TrapSprites>,
+    mut trigger_events: EventReader<TrapTriggerEvent>,
+    mut arrow_trap_query: Query<(&GlobalTransform, &mut ArrowTrap)>,
+) {
+    let triggered_ids: Vec<String> = trigger_events.read().map(|event| event.id.clone()).collect();
+
+    for (global_transform, mut arrow_trap) in &mut arrow_trap_query {
+        let fire = if arrow_trap.trigger.is_some() {
+            is_triggered(&arrow_trap.trigger, &triggered_ids)
+        } else {
+            arrow_trap.timer.tick(time.delta()).just_finished()
+        };
+        if !fire {
+            continue;
+        }
+
+        let origin = global_transform.translation().truncate() + arrow_trap.direction * ARROW_SPAWN_OFFSET;
+        spawn_arrow(&mut commands, &trap_sprites, origin, arrow_trap.direction, arrow_trap.damage);
+    }
+}
+
+fn spawn_arrow(
+    commands: &mut Commands,
+    trap_sprites: &TrapSprites,
+    origin: Vec2,
+    direction: Vec2,
+    damage: f32,
+) {
+    commands.spawn((
+        SpriteBundle {
+            texture: trap_sprites.arrow.clone(),
+            transform: Transform {
+                translation: origin.extend(5.0),
+                rotation: facing_rotation(direction),
+                scale: Vec3::new(SCALE / 2.0, SCALE / 2.0, 1.0),
+            },
+            ..Default::default()
+        },
+        TrapArrow {
+            damage,
+            lifetime: Timer::from_seconds(ARROW_LIFETIME_SECONDS, TimerMode::Once),
+        },
+        RigidBody::Dynamic,
+        Collider::cuboid(2.0, 5.0),
+        Faction::TrapProjectile,
+        Faction::TrapProjectile.collision_groups(),
+        Faction::TrapProjectile.solver_groups(),
+        ActiveEvents::COLLISION_EVENTS,
+        LockedAxes::ROTATION_LOCKED,
+        Velocity {
+            linvel: direction * ARROW_SPEED,
+            angvel: 0.0,
+        },
+        Name::new("Trap Arrow"),
+        OnGameScreen,
+    ));
+}
+
+fn update_damage_volumes(
+    time: Res<Time>,
+    mut volume_query: Query<(&GlobalTransform, &mut DamageVolume)>,
+    target_query: TrapTargetQuery,
+    mut status_query: Query<(&mut StatusEffects, Has<ControllablePlayer>, Has<Invulnerable>)>,
+    mut hit_events: EventWriter<HitEvent>,
+) {
+    for (global_transform, mut volume) in &mut volume_query {
+        if !volume.timer.tick(time.delta()).just_finished() {
+            continue;
+        }
+        let position = global_transform.translation().truncate();
+        let damage = Damage::new(volume.damage, volume.damage_type);
+        for target in occupants(position, volume.size, &target_query) {
+            if volume.damage_type == DamageType::Poison {
+                // Invulnerability frames keep poison out just like they keep out the hit
+                if let Ok((mut status_effects, is_player, false)) = status_query.get_mut(target) {
+                    status_effects.apply(if is_player { StatusEffect::player_poison() } else { StatusEffect::poison() });
+                }
+            }
+            // Lingering hazards hurt without pushing, so nobody gets knocked out of a volume into another
+            hit_events.send(HitEvent { knockback: 0.0, stun: 0.0, ..trap_hit(target, position, damage) });
+        }
+    }
+}
+
+fn arrow_collision(
+    mut commands: Commands,
+    mut collision_events: EventReader<FactionCollisionEvent>,
+    arrow_query: Query<(&Transform, &TrapArrow)>,
+    mut hit_events: EventWriter<HitEvent>,
+) {
+    for event in collision_events.read() {
+        if !event.started || event.faction != Faction::TrapProjectile {
+            continue;
+        }
+        let Ok((transform, arrow)) = arrow_query.get(event.entity) else {
+            continue;
+        };
+        if matches!(event.other_faction, Faction::Player | Faction::Enemy) {
+            hit_events.send(trap_hit(event.other, transform.translation.truncate(), Damage::new(arrow.damage, DamageType::Physical)));
+        } else if event.other_faction != Faction::Wall {
+            continue;
+        }
+        commands.entity(event.entity).despawn_recursive();
+    }
+}
+
+fn despawn_expired_arrows(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut arrow_query: Query<(Entity, &mut TrapArrow)>,
+) {
+    for (entity, mut arrow) in &mut arrow_query {
+        if arrow.lifetime.tick(time.delta()).just_finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}